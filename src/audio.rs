@@ -0,0 +1,228 @@
+use super::*;
+
+use model::SoundType;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+
+/// How many copies of the same sound can play at once.
+const MAX_VOICES: usize = 3;
+/// Voices are forgotten after this many seconds, which is longer than any effect.
+const VOICE_LIFETIME: f32 = 2.0;
+const MUSIC_FADE_TIME: f32 = 1.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Sfx,
+    Ui,
+    Music,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MusicTrack {
+    Menu,
+    Game,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioSettings {
+    pub master: f64,
+    pub sfx: f64,
+    pub ui: f64,
+    pub music: f64,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master: 1.0,
+            sfx: 0.2,
+            ui: 0.2,
+            music: 0.05,
+        }
+    }
+}
+
+struct Voice {
+    sound: SoundType,
+    effect: geng::SoundEffect,
+    age: f32,
+}
+
+struct Track {
+    track: MusicTrack,
+    effect: geng::SoundEffect,
+    /// Goes from 0 to 1 when fading in, and back to 0 when fading out.
+    fade: f32,
+    fading_out: bool,
+}
+
+struct Mixer {
+    settings: AutoSave<AudioSettings>,
+    voices: Vec<Voice>,
+    /// Sounds started since the last update, so that each plays at most once per frame.
+    played: Vec<SoundType>,
+    tracks: Vec<Track>,
+}
+
+pub struct Audio {
+    assets: Rc<Assets>,
+    mixer: RefCell<Mixer>,
+}
+
+impl Audio {
+    pub fn new(assets: &Rc<Assets>) -> Self {
+        Self {
+            assets: assets.clone(),
+            mixer: RefCell::new(Mixer {
+                settings: AutoSave::load(static_path().join("audio.json").to_str().unwrap()),
+                voices: vec![],
+                played: vec![],
+                tracks: vec![],
+            }),
+        }
+    }
+
+    pub fn play(&self, sound: SoundType) {
+        let mut mixer = self.mixer.borrow_mut();
+        if mixer.played.contains(&sound) {
+            return;
+        }
+        mixer.played.push(sound);
+
+        let playing = mixer
+            .voices
+            .iter()
+            .filter(|voice| voice.sound == sound)
+            .count();
+        if playing >= MAX_VOICES {
+            // Voices are ordered by age, so the first one is the oldest
+            let oldest = mixer
+                .voices
+                .iter()
+                .position(|voice| voice.sound == sound)
+                .unwrap();
+            mixer.voices.remove(oldest).effect.stop();
+        }
+
+        let asset = match sound {
+            SoundType::Hit => &self.assets.hit,
+            SoundType::Death => &self.assets.death,
+            SoundType::Movement => &self.assets.movement,
+            SoundType::Blip => &self.assets.blip,
+            SoundType::Select => &self.assets.select,
+            SoundType::Upgrade => &self.assets.upgrade,
+        };
+        let mut effect = asset.play();
+        effect.set_volume(mixer.settings.volume(sound.channel()));
+        mixer.voices.push(Voice {
+            sound,
+            effect,
+            age: 0.0,
+        });
+    }
+
+    /// Crossfades to the track, unless it is already playing.
+    pub fn play_music(&self, track: MusicTrack) {
+        let mut mixer = self.mixer.borrow_mut();
+        if mixer
+            .tracks
+            .iter()
+            .any(|playing| playing.track == track && !playing.fading_out)
+        {
+            return;
+        }
+        for playing in &mut mixer.tracks {
+            playing.fading_out = true;
+        }
+        let asset = match self.music(track) {
+            Some(asset) => asset,
+            None => return,
+        };
+        let mut effect = asset.play();
+        effect.set_volume(0.0);
+        mixer.tracks.push(Track {
+            track,
+            effect,
+            fade: 0.0,
+            fading_out: false,
+        });
+    }
+
+    // No tracks are assigned until the music is finished
+    fn music(&self, track: MusicTrack) -> Option<&geng::Sound> {
+        match track {
+            MusicTrack::Menu => None,
+            MusicTrack::Game => None,
+        }
+    }
+
+    pub fn settings(&self) -> AudioSettings {
+        (*self.mixer.borrow().settings).clone()
+    }
+
+    /// Saves the settings and applies them to everything that is playing.
+    pub fn set_settings(&self, settings: AudioSettings) {
+        let mut mixer = self.mixer.borrow_mut();
+        let mixer = &mut *mixer;
+        *mixer.settings = settings;
+        for voice in &mut mixer.voices {
+            voice
+                .effect
+                .set_volume(mixer.settings.volume(voice.sound.channel()));
+        }
+        let volume = mixer.settings.volume(Channel::Music);
+        for track in &mut mixer.tracks {
+            track.effect.set_volume(volume * track.fade as f64);
+        }
+    }
+
+    pub fn update(&self, delta_time: f32) {
+        let mut mixer = self.mixer.borrow_mut();
+        let mixer = &mut *mixer;
+        mixer.played.clear();
+
+        for voice in &mut mixer.voices {
+            voice.age += delta_time;
+        }
+        mixer.voices.retain(|voice| voice.age < VOICE_LIFETIME);
+
+        let volume = mixer.settings.volume(Channel::Music);
+        for track in &mut mixer.tracks {
+            let delta = delta_time / MUSIC_FADE_TIME;
+            track.fade = if track.fading_out {
+                track.fade - delta
+            } else {
+                track.fade + delta
+            }
+            .clamp(0.0, 1.0);
+            track.effect.set_volume(volume * track.fade as f64);
+        }
+        mixer.tracks.retain_mut(|track| {
+            let finished = track.fading_out && track.fade <= 0.0;
+            if finished {
+                track.effect.stop();
+            }
+            !finished
+        });
+    }
+}
+
+impl AudioSettings {
+    pub fn volume(&self, channel: Channel) -> f64 {
+        self.master
+            * match channel {
+                Channel::Sfx => self.sfx,
+                Channel::Ui => self.ui,
+                Channel::Music => self.music,
+            }
+    }
+}
+
+impl SoundType {
+    pub fn channel(&self) -> Channel {
+        match self {
+            Self::Hit | Self::Death | Self::Movement => Channel::Sfx,
+            Self::Blip | Self::Select | Self::Upgrade => Channel::Ui,
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use super::*;
+
+use model::{Command, Position};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Input {
+    Key(geng::Key),
+    Button(GamepadButton),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GamepadButton {
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    South,
+    East,
+    North,
+    West,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Binding {
+    pub command: Command,
+    pub inputs: Vec<Input>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputMap {
+    pub bindings: Vec<Binding>,
+    /// Opens and closes the pause menu, and backs out of the other menus.
+    pub menu: Vec<Input>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyPreset {
+    Arrows,
+    Wasd,
+    Vim,
+}
+
+impl InputMap {
+    pub fn new(preset: KeyPreset) -> Self {
+        use geng::Key;
+        let [left, right, down, up] = match preset {
+            KeyPreset::Arrows => [Key::Left, Key::Right, Key::Down, Key::Up],
+            KeyPreset::Wasd => [Key::A, Key::D, Key::S, Key::W],
+            KeyPreset::Vim => [Key::H, Key::L, Key::J, Key::K],
+        };
+        let binding = |command, key, button| Binding {
+            command,
+            inputs: vec![Input::Key(key), Input::Button(button)],
+        };
+        Self {
+            bindings: vec![
+                binding(Command::Move(vec2(-1, 0)), left, GamepadButton::DPadLeft),
+                binding(Command::Move(vec2(1, 0)), right, GamepadButton::DPadRight),
+                binding(Command::Move(vec2(0, -1)), down, GamepadButton::DPadDown),
+                binding(Command::Move(vec2(0, 1)), up, GamepadButton::DPadUp),
+                binding(Command::Ultimate, Key::Space, GamepadButton::West),
+                binding(Command::Confirm, Key::Enter, GamepadButton::South),
+                binding(Command::Restart, Key::R, GamepadButton::Select),
+            ],
+            menu: vec![Input::Key(Key::Escape), Input::Button(GamepadButton::Start)],
+        }
+    }
+
+    pub fn command(&self, input: Input) -> Option<Command> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.inputs.contains(&input))
+            .map(|binding| binding.command)
+    }
+
+    /// The direction for menu navigation, where the default bindings always work.
+    pub fn menu_direction(&self, input: Input) -> Option<Position> {
+        [self.command(input), Self::default().command(input)]
+            .into_iter()
+            .flatten()
+            .find_map(|command| match command {
+                Command::Move(direction) => Some(direction),
+                _ => None,
+            })
+    }
+
+    pub fn is_confirm(&self, input: Input) -> bool {
+        [self.command(input), Self::default().command(input)].contains(&Some(Command::Confirm))
+    }
+
+    pub fn is_menu(&self, input: Input) -> bool {
+        self.menu.contains(&input)
+    }
+
+    /// Binds the input to the binding with the index, or to the menu,
+    /// replacing the input of the same kind and unbinding it from everything else.
+    pub fn rebind(&mut self, binding: Option<usize>, input: Input) {
+        for inputs in self
+            .bindings
+            .iter_mut()
+            .map(|binding| &mut binding.inputs)
+            .chain(std::iter::once(&mut self.menu))
+        {
+            inputs.retain(|&other| other != input);
+        }
+        let inputs = match binding {
+            Some(index) => &mut self.bindings[index].inputs,
+            None => &mut self.menu,
+        };
+        let is_key = |input: &Input| matches!(input, Input::Key(_));
+        inputs.retain(|other| is_key(other) != is_key(&input));
+        if is_key(&input) {
+            inputs.insert(0, input);
+        } else {
+            inputs.push(input);
+        }
+    }
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        Self::new(KeyPreset::Arrows)
+    }
+}
+
+/// Polls the connected gamepads, only available on native.
+pub struct Gamepad {
+    #[cfg(not(target_arch = "wasm32"))]
+    gilrs: Option<gilrs::Gilrs>,
+}
+
+impl Default for Gamepad {
+    fn default() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            gilrs: match gilrs::Gilrs::new() {
+                Ok(gilrs) => Some(gilrs),
+                Err(error) => {
+                    log::warn!("Gamepads are not available: {}", error);
+                    None
+                }
+            },
+        }
+    }
+}
+
+impl Gamepad {
+    pub fn poll(&mut self) -> Vec<Input> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(gilrs) = &mut self.gilrs {
+            let mut inputs = vec![];
+            while let Some(gilrs::Event { event, .. }) = gilrs.next_event() {
+                if let gilrs::EventType::ButtonPressed(button, _) = event {
+                    inputs.extend(GamepadButton::from_gilrs(button).map(Input::Button));
+                }
+            }
+            return inputs;
+        }
+        vec![]
+    }
+}
+
+impl GamepadButton {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn from_gilrs(button: gilrs::Button) -> Option<Self> {
+        use gilrs::Button;
+        Some(match button {
+            Button::DPadUp => Self::DPadUp,
+            Button::DPadDown => Self::DPadDown,
+            Button::DPadLeft => Self::DPadLeft,
+            Button::DPadRight => Self::DPadRight,
+            Button::South => Self::South,
+            Button::East => Self::East,
+            Button::North => Self::North,
+            Button::West => Self::West,
+            Button::LeftTrigger => Self::LeftTrigger,
+            Button::RightTrigger => Self::RightTrigger,
+            Button::Select => Self::Select,
+            Button::Start => Self::Start,
+            _ => return None,
+        })
+    }
+}
@@ -0,0 +1,261 @@
+use super::*;
+
+impl Simulation {
+    /// `time_used` is the fraction of the move time the player used up this turn.
+    pub(super) fn update_director(&mut self, time_used: f32) {
+        self.director.turn += 1;
+        while let Some(wave) = self.director.waves.front() {
+            if wave.turn > self.director.turn {
+                break;
+            }
+            let wave = self.director.waves.pop_front().unwrap();
+            for group in &wave.groups {
+                for _ in 0..group.count {
+                    self.spawn_enemy(&group.enemy, group.from, wave.boss);
+                }
+            }
+        }
+
+        if self
+            .enemies
+            .iter()
+            .chain(self.spawns.iter().map(|spawn| &spawn.enemy))
+            .any(|enemy| enemy.is_boss)
+        {
+            return;
+        }
+
+        let pacing = &self.director.pacing;
+        let gain = pacing.budget_per_turn
+            * (1.0 + self.score as f32 * pacing.score_scaling)
+            * (1.0 - self.enemies.len() as f32 * pacing.enemy_pressure).max(0.0)
+            * (1.0 - time_used * pacing.time_pressure).max(0.0);
+        self.director.budget = (self.director.budget + gain).min(pacing.max_budget);
+
+        let options = self
+            .spawn_prefabs
+            .iter()
+            .filter_map(|(typ, prefab)| {
+                let cost = prefab.cost?;
+                (!self.locked_enemies.contains(typ)
+                    && self.score >= prefab.min_score
+                    && cost <= self.director.budget)
+                    .then(|| (typ.clone(), cost))
+            })
+            .collect::<Vec<_>>();
+        if let Some((typ, cost)) = options.choose(&mut self.rng) {
+            self.director.budget -= cost;
+            self.spawn_enemy(typ, None, false);
+        }
+    }
+
+    pub(super) fn update_spawns(&mut self) {
+        for spawn in &mut self.spawns {
+            spawn.turns_left -= 1;
+        }
+        let mut i = 0;
+        while i < self.spawns.len() {
+            let spawn = &self.spawns[i];
+            let (strategy, blocked, size) = (spawn.strategy, spawn.blocked, spawn.enemy.size);
+            if spawn.turns_left > 0 {
+                i += 1;
+                continue;
+            }
+            if spawn
+                .enemy
+                .tiles()
+                .any(|pos| self.enemies.iter().any(|enemy| enemy.occupies(pos)))
+            {
+                // Wait for the enemy to move away
+                self.spawns[i].turns_left = 1;
+                i += 1;
+                continue;
+            }
+            if spawn.enemy.occupies(self.player.position) {
+                match blocked {
+                    BlockedSpawn::Delay => {
+                        self.spawns[i].turns_left = 1;
+                        i += 1;
+                        continue;
+                    }
+                    BlockedSpawn::Relocate => {
+                        if let Some(pos) = self.spawn_point(strategy, size) {
+                            let spawn = &mut self.spawns[i];
+                            spawn.position = pos;
+                            spawn.enemy.position = pos;
+                            spawn.enemy.interpolation = Interpolation::new(pos.map(|x| x as f32));
+                        }
+                        self.spawns[i].turns_left = 1;
+                        i += 1;
+                        continue;
+                    }
+                    BlockedSpawn::Crush => self.kill_player(),
+                }
+            }
+            let spawn = self.spawns.remove(i);
+            self.enemies.push(spawn.enemy);
+        }
+    }
+
+    pub(super) fn update_minions(&mut self) {
+        let mut minions = Vec::new();
+        for enemy in &mut self.enemies {
+            if let Some(group) = &enemy.minions {
+                enemy.minion_timer -= 1;
+                if enemy.minion_timer <= 0 {
+                    enemy.minion_timer = group.every;
+                    minions.push(group.clone());
+                }
+            }
+        }
+        for group in minions {
+            for _ in 0..group.count {
+                self.spawn_enemy(&group.enemy, group.from, false);
+            }
+        }
+    }
+
+    fn spawn_enemy(&mut self, typ: &EnemyType, strategy: Option<SpawnStrategy>, is_boss: bool) {
+        let prefab = &self.spawn_prefabs[typ];
+        let strategy = strategy.unwrap_or(prefab.spawn);
+        let spawn_point = match self.spawn_point(strategy, prefab.size) {
+            Some(pos) => pos,
+            None => return,
+        };
+        let prefab = &self.spawn_prefabs[typ];
+        let mut enemy = prefab.enemy(typ, spawn_point, is_boss);
+        let (telegraph, blocked) = (prefab.telegraph, prefab.blocked);
+        for modifier in self.roll_modifiers(enemy.size) {
+            enemy.add_modifier(modifier);
+        }
+        self.spawns.push(Spawn {
+            position: spawn_point,
+            turns_left: telegraph,
+            strategy,
+            blocked,
+            enemy,
+        });
+    }
+
+    fn roll_modifiers(&mut self, size: Coord) -> Vec<Modifier> {
+        let elites = &self.director.elites;
+        let chance = (self.score as f32 * elites.chance_per_score).min(elites.max_chance);
+        let mut modifiers = Vec::new();
+        for _ in 0..elites.max_modifiers {
+            if !self.rng.gen_bool(chance as f64) {
+                break;
+            }
+            let options = elites
+                .modifiers
+                .iter()
+                .filter(|&&modifier| {
+                    // Copies are placed tile by tile
+                    !modifiers.contains(&modifier) && (size == 1 || modifier != Modifier::Splitting)
+                })
+                .collect::<Vec<_>>();
+            match options.choose(&mut self.rng) {
+                Some(&&modifier) => modifiers.push(modifier),
+                None => break,
+            }
+        }
+        modifiers
+    }
+
+    pub(super) fn split_enemy(&mut self, enemy: Enemy) {
+        let free = MovementType::Neighbour
+            .steps()
+            .into_iter()
+            .map(|delta| enemy.position + delta)
+            .filter(|&pos| {
+                self.arena.contains(pos)
+                    && self.arena.is_walkable(pos)
+                    && pos != self.player.position
+                    && !self.enemies.iter().any(|enemy| enemy.occupies(pos))
+                    && !self.spawns.iter().any(|spawn| spawn.enemy.occupies(pos))
+            })
+            .collect::<Vec<_>>();
+        let strategy = self.spawn_prefabs[&enemy.typ].spawn;
+        for &pos in free.choose_multiple(&mut self.rng, 2) {
+            let mut copy = enemy.clone();
+            copy.position = pos;
+            copy.interpolation = Interpolation::new(pos.map(|x| x as f32));
+            copy.max_health = (enemy.max_health / 2).max(1);
+            copy.health = copy.max_health;
+            copy.telegraph.clear();
+            copy.modifiers.clear();
+            copy.shield = false;
+            copy.is_dead = false;
+            self.spawns.push(Spawn {
+                position: pos,
+                turns_left: 1,
+                strategy,
+                blocked: BlockedSpawn::Delay,
+                enemy: copy,
+            });
+        }
+    }
+
+    fn spawn_point(&mut self, strategy: SpawnStrategy, size: Coord) -> Option<Position> {
+        // Positions at which the whole footprint fits
+        let bounds = AABB::from_corners(
+            self.arena.bounds.bottom_left(),
+            self.arena.bounds.top_right() - vec2(size - 1, size - 1),
+        );
+        let is_free = |pos: Position| {
+            footprint(pos, size).all(|pos| {
+                self.arena.contains(pos)
+                    && self.arena.is_walkable(pos)
+                    && pos != self.player.position
+                    && !self.enemies.iter().any(|enemy| enemy.occupies(pos))
+                    && !self.spawns.iter().any(|spawn| spawn.enemy.occupies(pos))
+            })
+        };
+        let tiles = (bounds.x_min..=bounds.x_max)
+            .flat_map(|x| (bounds.y_min..=bounds.y_max).map(move |y| vec2(x, y)));
+        let distance = |pos: Position| wrapped_distance(pos, self.player.position, &self.arena);
+
+        let options: Vec<Position> = match strategy {
+            SpawnStrategy::MapSpawns => {
+                let options = self
+                    .arena
+                    .spawns
+                    .iter()
+                    .filter(|&&(pos, _)| is_free(pos))
+                    .copied()
+                    .collect::<Vec<_>>();
+                return options
+                    .choose_weighted(&mut self.rng, |&(_, weight)| weight)
+                    .ok()
+                    .map(|&(pos, _)| pos);
+            }
+            SpawnStrategy::Corners => bounds.corners().into_iter().collect(),
+            SpawnStrategy::Edges => tiles
+                .filter(|pos| {
+                    pos.x == bounds.x_min
+                        || pos.x == bounds.x_max
+                        || pos.y == bounds.y_min
+                        || pos.y == bounds.y_max
+                })
+                .collect(),
+            SpawnStrategy::North => tiles.filter(|pos| pos.y == bounds.y_max).collect(),
+            SpawnStrategy::South => tiles.filter(|pos| pos.y == bounds.y_min).collect(),
+            SpawnStrategy::East => tiles.filter(|pos| pos.x == bounds.x_max).collect(),
+            SpawnStrategy::West => tiles.filter(|pos| pos.x == bounds.x_min).collect(),
+            SpawnStrategy::Farthest => {
+                let free = tiles.filter(|&pos| is_free(pos)).collect::<Vec<_>>();
+                let max = free.iter().map(|&pos| distance(pos)).max()?;
+                free.into_iter()
+                    .filter(|&pos| distance(pos) == max)
+                    .collect()
+            }
+            SpawnStrategy::Ring { distance: ring } => {
+                tiles.filter(|&pos| distance(pos) == ring).collect()
+            }
+        };
+        let options = options
+            .into_iter()
+            .filter(|&pos| is_free(pos))
+            .collect::<Vec<_>>();
+        options.choose(&mut self.rng).copied()
+    }
+}
@@ -40,7 +40,7 @@ impl Interpolation {
         self.targets.push_back(jump);
     }
 
-    pub fn update(&mut self, delta_time: f32) {
+    pub fn update(&mut self, delta_time: f32, max_time: f32) {
         let targets = &self.targets[0];
         if targets.is_empty() && self.targets.len() > 1 {
             // Jump
@@ -57,7 +57,7 @@ impl Interpolation {
             let delta = next - self.current;
             let distance = delta.len();
             let max_speed = (INTERPOLATION_MIN_SPEED)
-                .max((distance + self.targets_distance) / INTERPOLATION_MAX_TIME)
+                .max((distance + self.targets_distance) / max_time)
                 * delta_time;
             if distance <= max_speed {
                 // Reached target
@@ -1,11 +1,46 @@
+use std::collections::HashSet;
+
 use super::*;
 use model::*;
 
+mod director;
 mod interpolation;
+mod movement;
+mod pathfinding;
 
 pub use interpolation::*;
+pub use movement::*;
+pub use pathfinding::*;
+
+impl Simulation {
+    pub fn update_interpolation(&mut self, delta_time: f32, max_time: f32) {
+        self.player.interpolation.update(delta_time, max_time);
+        for enemy in &mut self.enemies {
+            enemy.interpolation.update(delta_time, max_time);
+        }
+    }
+
+    pub fn update_move_timer(&mut self, delta_time: f32) {
+        if self.player.is_dead {
+            return;
+        }
+
+        if self.upgrade_menu.is_none() {
+            if !self.freeze_move_timer {
+                self.move_time_left -= delta_time
+                    * if self.using_ultimate.is_some() {
+                        0.5
+                    } else {
+                        1.0
+                    };
+            }
+            if self.move_time_left <= 0.0 {
+                self.move_time_left = 0.0;
+                self.kill_player();
+            }
+        }
+    }
 
-impl GameState {
     pub fn tick(&mut self, player_move: Position) {
         if self.player.is_dead {
             return;
@@ -21,171 +56,442 @@ impl GameState {
                 choice = min;
             }
             upgrade_menu.choice = choice as usize;
-            self.play_sound(self.assets.blip.play());
+            self.events.push(Event::Sound(SoundType::Blip));
             return;
         }
 
         // Move player
-        self.play_sound(self.assets.movement.play());
-        self.freeze_move_timer = false;
         let old_pos = self.player.position;
-        let pos = self.player.position + player_move;
-        let (mut pos, jump) = wrap_pos(pos, self.arena_bounds);
+        let (mut pos, mut jump) = match self.arena.step(old_pos, player_move) {
+            Some(step) => step,
+            None => return,
+        };
         if let Some(origin) = self.using_ultimate {
             pos = clamp_wrapped_pos(
                 pos,
                 self.player_ultimate.boundary().translate(origin),
-                self.arena_bounds,
+                self.arena.bounds,
             );
+            if !self.arena.is_walkable(pos) {
+                return;
+            }
+        } else if self.arena.tile(pos) == Tile::Wall {
+            return;
         }
+        self.events.push(Event::Sound(SoundType::Movement));
+        self.freeze_move_timer = false;
 
+        if self.using_ultimate.is_none() && self.player.slowed {
+            // Spend the turn getting out of the slow tile
+            self.player.slowed = false;
+            pos = old_pos;
+            jump = false;
+        }
         self.player.position = pos;
-        if jump {
-            let jump_dir = pos - old_pos;
-            let jump_dir =
-                vec2(jump_dir.x.signum(), jump_dir.y.signum()).map(|x| x as f32) * TILE_SIZE / 2.0;
-            self.player
-                .interpolation
-                .queue(old_pos.map(|x| x as f32) - jump_dir);
-            self.player
-                .interpolation
-                .queue_jump(pos.map(|x| x as f32) + jump_dir);
-        }
-        self.player.interpolation.queue(pos.map(|x| x as f32));
+        queue_move(&mut self.player.interpolation, old_pos, pos, jump);
 
         if self.using_ultimate.is_some() {
             return;
         }
 
+        match self.arena.tile(pos) {
+            Tile::Pit => {
+                self.kill_player();
+                return;
+            }
+            Tile::Slow if pos != old_pos => {
+                self.player.slowed = true;
+            }
+            _ => {}
+        }
+
+        let time_used = 1.0 - self.move_time_left / self.move_time_limit;
         self.move_time_left = self.move_time_limit;
         self.damages = vec![];
 
         // self.player_collide();
 
         // Move enemies
-        for enemy in &mut self.enemies {
-            let delta = self.player.position - enemy.position;
-            enemy.position = clamp_pos(
-                enemy.move_towards(delta),
-                self.arena_bounds,
-            );
-            enemy.interpolation.queue(enemy.position.map(|x| x as f32));
+        let frozen = self.time_stop > 0;
+        if !frozen {
+            for id in 0..self.enemies.len() {
+                if self.enemies[id].size > 1 {
+                    self.move_large_enemy(id);
+                    if self.enemies[id].has_modifier(Modifier::Hasty) {
+                        self.move_large_enemy(id);
+                    }
+                }
+            }
+            self.move_enemies(false);
+            self.move_enemies(true);
         }
 
         self.player_collide();
 
+        for enemy in &mut self.enemies {
+            enemy.armor_used = false;
+            if !frozen {
+                enemy.regenerate();
+            }
+        }
+
+        if let Some(decoy) = &mut self.decoy {
+            decoy.turns_left -= 1;
+            if decoy.turns_left <= 0
+                || self
+                    .enemies
+                    .iter()
+                    .any(|enemy| enemy.occupies(decoy.position))
+            {
+                self.decoy = None;
+            }
+        }
+
         // Player actions
-        let mut attack_positions = Vec::new();
+        let mut hits = Vec::new();
         let mut attacked = false;
         for attack in &mut self.player_attacks {
             if attack.action.update(1) {
                 attacked = true;
                 attack.action.set_on_cooldown();
-                attack_positions.extend(attack.attack_positions(self.player.position));
+                let positions = attack
+                    .attack_positions(self.player.position)
+                    .filter(|&pos| self.arena.line_of_sight(self.player.position, pos))
+                    .collect();
+                hits.push((positions, attack.damage));
             }
         }
-        self.player_ultimate.action.update(1);
-        self.attack_positions(Caster::Player, &attack_positions);
+        self.player_ultimate.action_mut().update(1);
+        self.attack_positions(Caster::Player, &hits);
         if attacked {
-            self.play_sound(self.assets.hit.play());
+            self.events.push(Event::Sound(SoundType::Hit));
         }
 
-        // Count siblings
-        let mut siblings = HashMap::new();
-        for (enemy_type, _) in &self.spawn_prefabs {
-            siblings.insert(enemy_type.clone(), 0);
-        }
-        for enemy in &self.enemies {
-            *siblings.get_mut(&enemy.typ).unwrap() += 1;
+        if frozen {
+            // Enemies and spawns wait for the time to resume
+            self.time_stop -= 1;
+            return;
         }
 
-        // Spawn new enemies
-        self.enemies
-            .extend(self.spawns.drain(..).map(|(_, enemy)| enemy));
-        for (enemy_type, prefab) in self
-            .spawn_prefabs
+        // Enemy actions
+        let telegraphs = self
+            .enemies
             .iter_mut()
-            .filter(|(_, prefab)| self.score >= prefab.min_score)
-        {
-            prefab.next_spawn -= 1;
-            if prefab.next_spawn <= 0 {
-                let sibs = siblings.get_mut(enemy_type).unwrap();
-                *sibs += 1;
-                prefab.refresh_cooldown(*sibs);
-                let spawn_points = self.arena_bounds.corners();
-                let &spawn_point = spawn_points
-                    .choose(&mut global_rng())
-                    .expect("Failed to find a spawn point");
-                let enemy = Enemy {
-                    typ: enemy_type.clone(),
-                    color: prefab.color,
-                    position: spawn_point,
-                    interpolation: Interpolation::new(spawn_point.map(|x| x as f32)),
-                    movement: prefab.movement.clone(),
-                    is_dead: false,
-                };
-                self.spawns.push((spawn_point, enemy));
+            .enumerate()
+            .filter(|(_, enemy)| !enemy.telegraph.is_empty())
+            .map(|(id, enemy)| {
+                let damage = enemy.attack.as_ref().map_or(1, |attack| attack.damage);
+                (id, vec![(std::mem::take(&mut enemy.telegraph), damage)])
+            })
+            .collect::<Vec<_>>();
+        if !telegraphs.is_empty() {
+            self.events.push(Event::Sound(SoundType::Hit));
+        }
+        for (id, hits) in telegraphs {
+            self.attack_positions(Caster::Enemy { id }, &hits);
+        }
+        self.remove_dead_enemies(false);
+
+        let target = self.enemy_target();
+        for enemy in &mut self.enemies {
+            if let Some(attack) = &mut enemy.attack {
+                if attack.action.update(1) {
+                    let mut positions = if enemy.size > 1 {
+                        // Large enemies use area attacks aligned with their footprint
+                        attack.attack_positions(enemy.position).collect()
+                    } else {
+                        attack.directed_positions(enemy.position, target - enemy.position)
+                    };
+                    positions.retain(|&pos| self.arena.line_of_sight(enemy.position, pos));
+                    if positions.contains(&target) {
+                        attack.action.set_on_cooldown();
+                        enemy.telegraph = positions;
+                    }
+                }
             }
         }
+
+        // Spawn new enemies
+        self.update_minions();
+        self.update_spawns();
+        self.update_director(time_used);
+    }
+
+    /// Moves the single-tile enemies, or only the hasty ones for their extra move.
+    fn move_enemies(&mut self, hasty_only: bool) {
+        let requests = (0..self.enemies.len())
+            .filter(|&id| self.enemies[id].size == 1)
+            .map(|id| {
+                if hasty_only && !self.enemies[id].has_modifier(Modifier::Hasty) {
+                    // Stand still, keeping the tile reserved
+                    MoveRequest {
+                        id,
+                        from: self.enemies[id].position,
+                        candidates: vec![],
+                        priority: (0, 0),
+                    }
+                } else {
+                    self.move_request(id)
+                }
+            })
+            .collect();
+        for (id, pos) in resolve_moves(requests) {
+            let enemy = &mut self.enemies[id];
+            let old_pos = enemy.position;
+            let jump = old_pos + wrapped_delta(old_pos, pos, &self.arena) != pos;
+            enemy.slowed = pos != old_pos && self.arena.tile(pos) == Tile::Slow;
+            enemy.position = pos;
+            queue_move(&mut enemy.interpolation, old_pos, pos, jump);
+        }
+    }
+
+    /// Large enemies move before the others, one step at a time,
+    /// and only if their whole footprint fits.
+    fn move_large_enemy(&mut self, id: Id) {
+        let enemy = &self.enemies[id];
+        if !enemy.telegraph.is_empty() {
+            return;
+        }
+        let size = enemy.size;
+        let from = enemy.position;
+        let target = self.enemy_target();
+        let bounds = AABB::from_corners(
+            self.arena.bounds.bottom_left(),
+            self.arena.bounds.top_right() - vec2(size - 1, size - 1),
+        );
+        let distance = |pos: Position| {
+            let closest = clamp_pos(
+                target,
+                AABB::from_corners(pos, pos + vec2(size - 1, size - 1)),
+            );
+            (target - closest).x.abs() + (target - closest).y.abs()
+        };
+        let movement = enemy.movement.clone();
+        let primary = clamp_pos(self.enemies[id].move_towards(target - from), bounds);
+        let fits = |pos: Position| {
+            footprint(pos, size).all(|tile| {
+                self.arena.is_walkable(tile)
+                    && !self
+                        .enemies
+                        .iter()
+                        .enumerate()
+                        .any(|(other, enemy)| other != id && enemy.occupies(tile))
+            })
+        };
+        let current_distance = distance(from);
+        let steps = if movement.approaches() {
+            movement.steps()
+        } else {
+            // Only approaching units can dodge towards the target
+            vec![]
+        };
+        let mut sidesteps = steps
+            .into_iter()
+            .map(|delta| clamp_pos(from + delta, bounds))
+            .filter(|&pos| distance(pos) < current_distance)
+            .collect::<Vec<_>>();
+        sidesteps.sort_by_key(|&pos| (distance(pos), pos.x, pos.y));
+        let pos = std::iter::once(primary)
+            .chain(sidesteps)
+            .find(|&pos| pos != from && fits(pos));
+
+        if let Some(pos) = pos {
+            let enemy = &mut self.enemies[id];
+            enemy.position = pos;
+            queue_move(&mut enemy.interpolation, from, pos, false);
+        }
+    }
+
+    fn move_request(&mut self, id: Id) -> MoveRequest {
+        let occupied = self
+            .enemies
+            .iter()
+            .flat_map(|enemy| enemy.tiles())
+            .collect::<HashSet<_>>();
+        let large = self
+            .enemies
+            .iter()
+            .filter(|enemy| enemy.size > 1)
+            .flat_map(|enemy| enemy.tiles())
+            .collect::<HashSet<_>>();
+        let target = self.enemy_target();
+        let arena = &self.arena;
+        let enemy = &mut self.enemies[id];
+        let from = enemy.position;
+        let mut request = MoveRequest {
+            id,
+            from,
+            candidates: vec![],
+            priority: (0, 0),
+        };
+        if !enemy.telegraph.is_empty() {
+            // Enemies stand still while preparing an attack
+            return request;
+        }
+        if enemy.slowed {
+            enemy.slowed = false;
+            return request;
+        }
+
+        let distance = |pos: Position| wrapped_distance(pos, target, arena);
+        let movement = enemy.movement.clone();
+        let primary = if enemy.pathfinding {
+            let next = next_step(from, target, &movement, arena, |pos| {
+                occupied.contains(&pos)
+            });
+            enemy.movement.advance();
+            next
+        } else {
+            wrapped_step(&mut enemy.movement, from, target, arena)
+        };
+        let primary = match primary {
+            Some(pos) if pos != from => pos,
+            _ => return request,
+        };
+
+        let step = wrapped_delta(from, primary, arena);
+        let speed = step.x.abs().max(step.y.abs());
+        let current_distance = distance(from);
+        let steps = if movement.approaches() {
+            movement.steps()
+        } else {
+            vec![]
+        };
+        let mut sidesteps = steps
+            .into_iter()
+            .filter_map(|delta| arena.step(from, delta).map(|(pos, _)| pos))
+            .filter(|&pos| {
+                pos != primary
+                    && arena.is_walkable(pos)
+                    && !large.contains(&pos)
+                    && distance(pos) < current_distance
+            })
+            .collect::<Vec<_>>();
+        sidesteps.sort_by_key(|&pos| (distance(pos), pos.x, pos.y));
+        sidesteps.dedup();
+
+        request.candidates = std::iter::once(primary)
+            .filter(|&pos| arena.is_walkable(pos) && !large.contains(&pos))
+            .chain(sidesteps)
+            .collect();
+        request.priority = (-speed, current_distance);
+        request
+    }
+
+    pub fn attack_slots(&self) -> usize {
+        attack_slots(self.highscore).min(self.max_attack_slots)
+    }
+
+    /// The position enemies move towards and attack: the decoy if there is one.
+    fn enemy_target(&self) -> Position {
+        self.decoy
+            .as_ref()
+            .map_or(self.player.position, |decoy| decoy.position)
     }
 
     fn player_collide(&mut self) {
         if self
             .enemies
             .iter()
-            .any(|enemy| enemy.position == self.player.position)
+            .any(|enemy| enemy.occupies(self.player.position))
         {
             self.kill_player();
         }
     }
 
-    pub fn play_sound(&mut self, mut sound: geng::SoundEffect) {
-        sound.set_volume(self.volume);
-    }
-
     pub fn kill_player(&mut self) {
+        if self.player.is_dead {
+            return;
+        }
         self.player.is_dead = true;
-        self.play_sound(self.assets.death.play());
+        self.events.push(Event::Sound(SoundType::Death));
     }
 
-    fn get_in_point(&self, position: Position) -> Option<Caster> {
-        let mut units = std::iter::once((Caster::Player, self.player.position)).chain(
-            self.enemies
-                .iter()
-                .enumerate()
-                .map(|(id, enemy)| (Caster::Enemy { id }, enemy.position)),
-        );
-        units
-            .find(|(_, unit_pos)| *unit_pos == position)
-            .map(|(caster, _)| caster)
-    }
-
-    fn attack_positions(&mut self, caster: Caster, positions: &[Position]) {
-        self.damages.extend(positions);
-        match caster {
-            Caster::Player => {
-                for enemy in &mut self.enemies {
-                    if positions.contains(&enemy.position) {
-                        enemy.is_dead = true;
+    fn get_in_points(&self, positions: &[Position]) -> Vec<Caster> {
+        let player = positions.contains(&self.player.position);
+        std::iter::once(Caster::Player)
+            .filter(|_| player)
+            .chain(
+                self.enemies
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, enemy)| positions.iter().any(|&pos| enemy.occupies(pos)))
+                    .map(|(id, _)| Caster::Enemy { id }),
+            )
+            .collect()
+    }
+
+    /// Every unit is hit at most once by each attack, no matter how many tiles it covers.
+    fn attack_positions(&mut self, caster: Caster, hits: &[(Vec<Position>, Health)]) {
+        for (positions, damage) in hits {
+            self.damages
+                .extend(positions.iter().map(|&position| (caster, position)));
+            if caster != Caster::Player
+                && self
+                    .decoy
+                    .as_ref()
+                    .is_some_and(|decoy| positions.contains(&decoy.position))
+            {
+                self.decoy = None;
+            }
+            for target in self.get_in_points(positions) {
+                match (caster, target) {
+                    (Caster::Player | Caster::Explosion, Caster::Enemy { id }) => {
+                        self.enemies[id].take_hit(*damage);
                     }
+                    (Caster::Enemy { .. }, Caster::Player) => {
+                        self.kill_player();
+                    }
+                    (Caster::Enemy { id }, Caster::Enemy { id: target }) if target != id => {
+                        self.enemies[target].take_hit(*damage);
+                    }
+                    _ => {}
                 }
-                let mut lvl_ups = 0;
-                self.enemies.retain(|enemy| {
-                    if enemy.is_dead {
-                        self.score += 1;
-                        *self.highscore = (*self.highscore).max(self.score);
-                        lvl_ups += self.experience.add_exp(1);
-                        self.spawn_prefabs
-                            .get_mut(&enemy.typ)
-                            .unwrap()
-                            .killed_siblings += 1;
+            }
+        }
+
+        if caster == Caster::Player {
+            self.remove_dead_enemies(true);
+        }
+        // Enemies killed by other enemies are removed by the caller, so that ids stay valid
+    }
+
+    /// Only kills made by the player are `rewarded` with score and experience.
+    fn remove_dead_enemies(&mut self, rewarded: bool) {
+        let mut lvl_ups = 0;
+        loop {
+            let mut explosions = Vec::new();
+            let mut splits = Vec::new();
+            self.enemies.retain(|enemy| {
+                if enemy.is_dead {
+                    if rewarded {
+                        *self.kills.entry(enemy.typ.clone()).or_default() += 1;
+                        self.score += enemy.reward;
+                        self.highscore = self.highscore.max(self.score);
+                        lvl_ups += self.experience.add_exp(enemy.reward);
                     }
-                    !enemy.is_dead
-                });
-                self.upgrade(lvl_ups);
+                    if enemy.has_modifier(Modifier::Explosive) {
+                        let around = footprint(enemy.position - vec2(1, 1), enemy.size + 2)
+                            .filter(|&pos| self.arena.contains(pos) && !enemy.occupies(pos))
+                            .collect();
+                        explosions.push((around, EXPLOSION_DAMAGE));
+                    }
+                    if enemy.has_modifier(Modifier::Splitting) {
+                        splits.push(enemy.clone());
+                    }
+                }
+                !enemy.is_dead
+            });
+            for enemy in splits {
+                self.split_enemy(enemy);
+            }
+            if explosions.is_empty() {
+                break;
             }
-            Caster::Enemy { id } => todo!(),
+            self.events.push(Event::Sound(SoundType::Hit));
+            self.attack_positions(Caster::Explosion, &explosions);
         }
+        self.upgrade(lvl_ups);
     }
 
     pub fn use_ultimate(&mut self) {
@@ -196,14 +502,96 @@ impl GameState {
         if self.using_ultimate.is_some() {
             self.move_time_left = self.move_time_limit;
             self.using_ultimate = None;
-        } else if self.upgrade_menu.is_none() && self.player_ultimate.action.is_ready() {
-            self.using_ultimate = Some(self.player.position);
-            self.player_ultimate.action.set_on_cooldown();
+            return;
+        }
+        if self.upgrade_menu.is_some() || !self.player_ultimate.action().is_ready() {
+            return;
+        }
+
+        let player_pos = self.player.position;
+        match self.player_ultimate {
+            Ultimate::Teleport { .. } => {
+                self.using_ultimate = Some(player_pos);
+            }
+            Ultimate::TimeStop { turns, .. } => {
+                self.time_stop = turns;
+            }
+            Ultimate::Shockwave { damage, .. } => {
+                let positions = self
+                    .player_ultimate
+                    .deltas()
+                    .filter(|&delta| delta != Vec2::ZERO)
+                    .filter_map(|delta| self.arena.wrap(player_pos + delta))
+                    .map(|(pos, _)| pos)
+                    .filter(|&pos| self.arena.line_of_sight(player_pos, pos))
+                    .collect();
+                self.damages = vec![];
+                self.attack_positions(Caster::Player, &[(positions, damage)]);
+                self.events.push(Event::Sound(SoundType::Hit));
+            }
+            Ultimate::Swap { .. } => {
+                // Swap with the closest single-tile enemy
+                let target = self
+                    .player_ultimate
+                    .deltas()
+                    .filter(|&delta| delta != Vec2::ZERO)
+                    .filter_map(|delta| {
+                        let (pos, jump) = self.arena.wrap(player_pos + delta)?;
+                        let id = self
+                            .enemies
+                            .iter()
+                            .position(|enemy| enemy.size == 1 && enemy.position == pos)?;
+                        Some((delta.x.abs().max(delta.y.abs()), id, jump))
+                    })
+                    .min_by_key(|&(distance, id, _)| (distance, id));
+                let (id, jump) = match target {
+                    Some((_, id, jump)) => (id, jump),
+                    None => return,
+                };
+                let enemy = &mut self.enemies[id];
+                let enemy_pos = enemy.position;
+                enemy.position = player_pos;
+                enemy.telegraph.clear();
+                queue_move(&mut enemy.interpolation, enemy_pos, player_pos, jump);
+                self.player.position = enemy_pos;
+                queue_move(&mut self.player.interpolation, player_pos, enemy_pos, jump);
+                self.events.push(Event::Sound(SoundType::Movement));
+            }
+            Ultimate::Decoy { turns, .. } => {
+                self.decoy = Some(Decoy {
+                    position: player_pos,
+                    turns_left: turns,
+                });
+            }
+        }
+        self.player_ultimate.action_mut().set_on_cooldown();
+    }
+
+    pub fn teleport(&mut self, target: Position) {
+        let origin = match self.using_ultimate {
+            Some(origin) if !self.player.is_dead => origin,
+            _ => return,
+        };
+        let reachable = self
+            .player_ultimate
+            .deltas()
+            .filter_map(|delta| self.arena.wrap(origin + delta))
+            .any(|(pos, _)| pos == target);
+        if !reachable || !self.arena.is_walkable(target) {
+            return;
         }
+        let old_pos = self.player.position;
+        self.player.position = target;
+        queue_move(&mut self.player.interpolation, old_pos, target, false);
+        self.events.push(Event::Sound(SoundType::Movement));
+        self.freeze_move_timer = false;
+        // Picking the tile also confirms it
+        self.use_ultimate();
     }
 
     fn upgrade(&mut self, lvl_ups: usize) {
         if lvl_ups > 0 {
+            let attack_slots = self.attack_slots();
             let options = self
                 .upgrades
                 .iter_mut()
@@ -211,12 +599,15 @@ impl GameState {
                     Upgrade::Global { info } => {
                         let meet_requirement = typ
                             .requirement(info.current)
-                            .check(self.score, attack_slots(*self.highscore));
+                            .check(self.score, attack_slots);
                         if meet_requirement && info.current < info.max {
                             match typ {
                                 UpgradeType::NewAttack => {
+                                    if self.player_attacks.len() >= attack_slots {
+                                        return None;
+                                    }
                                     let attack_index =
-                                        (0..self.potential_attacks.len()).choose(&mut global_rng());
+                                        (0..self.potential_attacks.len()).choose(&mut self.rng);
                                     attack_index.map(|i| (typ, Some(i)))
                                 }
                                 _ => Some((typ, None)),
@@ -239,76 +630,126 @@ impl GameState {
                                         UpgradeType::ReduceAttackCooldown => {
                                             self.player_attacks[*i].action.cooldown > 1
                                         }
+                                        UpgradeType::UpgradeAttack => {
+                                            self.player_attacks[*i].upgrade.is_some()
+                                        }
                                         _ => true,
                                     }
                             })
                             .map(|(i, _)| i)
-                            .choose(&mut global_rng());
+                            .choose(&mut self.rng);
                         attack_index.map(|i| ((typ, Some(i))))
                     }
-                });
-            let options = options.choose_multiple(&mut global_rng(), 3);
+                })
+                .collect::<Vec<_>>();
+            let mut options = options.into_iter().choose_multiple(&mut self.rng, 3);
+            if options.is_empty() {
+                // Everything is maxed out
+                options.push((UpgradeType::BonusScore, None));
+            }
             self.upgrade_menu = Some(UpgradeMenu {
                 lvl_ups_left: lvl_ups,
                 options,
                 choice: 0,
             });
 
-            self.play_sound(self.assets.upgrade.play());
+            self.events.push(Event::Sound(SoundType::Upgrade));
         }
     }
 
+    pub fn choose_upgrade(&mut self, choice: usize) {
+        match &mut self.upgrade_menu {
+            Some(menu) if choice < menu.options.len() => menu.choice = choice,
+            _ => return,
+        }
+        self.select_upgrade();
+    }
+
     pub fn select_upgrade(&mut self) {
         if let Some(mut menu) = self.upgrade_menu.take() {
-            if let Some((upgrade_type, attack_index)) = menu.options.get(menu.choice) {
-                if let Some(upgrade) = self.upgrades.get_mut(upgrade_type) {
-                    match upgrade_type {
-                        UpgradeType::NewAttack => {
-                            let attack = self.potential_attacks.remove(attack_index.unwrap());
-                            self.player_attacks.push(attack);
-                        }
-                        UpgradeType::IncUltRadius => {
-                            self.player_ultimate.radius += 1;
-                        }
-                        UpgradeType::ReduceUltCooldown => {
-                            self.player_ultimate.action.cooldown -= 1;
-                        }
-                        UpgradeType::IncDeathTimer => {
-                            self.move_time_limit += 2.0;
-                        }
-                        UpgradeType::ReduceAttackCooldown => {
-                            self.player_attacks
-                                .get_mut(attack_index.unwrap())
-                                .unwrap()
-                                .action
-                                .cooldown -= 1;
-                        }
-                        UpgradeType::UpgradeAttack => {
-                            self.player_attacks
-                                .get_mut(attack_index.unwrap())
-                                .unwrap()
-                                .upgrade();
-                        }
+            if let Some(&(upgrade_type, attack_index)) = menu.options.get(menu.choice) {
+                match upgrade_type {
+                    UpgradeType::NewAttack => {
+                        let attack = self.potential_attacks.remove(attack_index.unwrap());
+                        self.player_attacks.push(attack);
                     }
-
-                    match upgrade {
-                        Upgrade::Global { info, .. } => {
-                            info.current += 1;
+                    UpgradeType::IncUltRadius => match &mut self.player_ultimate {
+                        Ultimate::Teleport { radius, .. }
+                        | Ultimate::Shockwave { radius, .. }
+                        | Ultimate::Swap { radius, .. } => *radius += 1,
+                        Ultimate::TimeStop { .. } | Ultimate::Decoy { .. } => {}
+                    },
+                    UpgradeType::IncUltDuration => match &mut self.player_ultimate {
+                        Ultimate::TimeStop { turns, .. } | Ultimate::Decoy { turns, .. } => {
+                            *turns += 1
                         }
-                        Upgrade::Attack { info } => {
-                            info.get_mut(attack_index.unwrap()).unwrap().current += 1;
+                        _ => {}
+                    },
+                    UpgradeType::IncUltDamage => {
+                        if let Ultimate::Shockwave { damage, .. } = &mut self.player_ultimate {
+                            *damage += 1;
                         }
                     }
+                    UpgradeType::ReduceUltCooldown => {
+                        self.player_ultimate.action_mut().cooldown -= 1;
+                    }
+                    UpgradeType::IncDeathTimer => {
+                        self.move_time_limit += 2.0;
+                    }
+                    UpgradeType::ReduceAttackCooldown => {
+                        self.player_attacks
+                            .get_mut(attack_index.unwrap())
+                            .unwrap()
+                            .action
+                            .cooldown -= 1;
+                    }
+                    UpgradeType::IncAttackDamage => {
+                        self.player_attacks
+                            .get_mut(attack_index.unwrap())
+                            .unwrap()
+                            .damage += 1;
+                    }
+                    UpgradeType::UpgradeAttack => {
+                        self.player_attacks
+                            .get_mut(attack_index.unwrap())
+                            .unwrap()
+                            .upgrade();
+                    }
+                    UpgradeType::BonusScore => {
+                        self.score += BONUS_SCORE;
+                        self.highscore = self.highscore.max(self.score);
+                    }
+                }
 
-                    menu.lvl_ups_left -= 1;
-                    self.upgrade(menu.lvl_ups_left);
-                    self.play_sound(self.assets.select.play());
+                match self.upgrades.get_mut(&upgrade_type) {
+                    Some(Upgrade::Global { info, .. }) => {
+                        info.current += 1;
+                    }
+                    Some(Upgrade::Attack { info }) => {
+                        info.get_mut(attack_index.unwrap()).unwrap().current += 1;
+                    }
+                    None => {}
                 }
+
+                menu.lvl_ups_left -= 1;
+                self.upgrade(menu.lvl_ups_left);
+                self.events.push(Event::Sound(SoundType::Select));
             }
         }
     }
 }
 
+pub fn queue_move(interpolation: &mut Interpolation, old_pos: Position, pos: Position, jump: bool) {
+    if jump {
+        let jump_dir = pos - old_pos;
+        let jump_dir =
+            vec2(jump_dir.x.signum(), jump_dir.y.signum()).map(|x| x as f32) * TILE_SIZE / 2.0;
+        interpolation.queue(old_pos.map(|x| x as f32) - jump_dir);
+        interpolation.queue_jump(pos.map(|x| x as f32) + jump_dir);
+    }
+    interpolation.queue(pos.map(|x| x as f32));
+}
+
 pub fn clamp_pos(pos: Position, aabb: AABB<Coord>) -> Position {
     vec2(
         pos.x.clamp(aabb.x_min, aabb.x_max),
@@ -316,12 +757,6 @@ pub fn clamp_pos(pos: Position, aabb: AABB<Coord>) -> Position {
     )
 }
 
-pub fn wrap_pos(pos: Position, bounds: AABB<Coord>) -> (Position, bool) {
-    let (x, jump_x) = wrap_coord(pos.x, vec2(bounds.x_min, bounds.x_max));
-    let (y, jump_y) = wrap_coord(pos.y, vec2(bounds.y_min, bounds.y_max));
-    (vec2(x, y), jump_x || jump_y)
-}
-
 pub fn wrap_coord(mut pos: Coord, bounds: Vec2<Coord>) -> (Coord, bool) {
     let width = bounds.y - bounds.x + 1;
     let mut jump = false;
@@ -336,6 +771,10 @@ pub fn wrap_coord(mut pos: Coord, bounds: Vec2<Coord>) -> (Coord, bool) {
     (pos, jump)
 }
 
+pub fn footprint(position: Position, size: Coord) -> impl Iterator<Item = Position> {
+    (0..size).flat_map(move |x| (0..size).map(move |y| position + vec2(x, y)))
+}
+
 pub fn grid_cell_aabb(cell_pos: Position, tile_size: Vec2<f32>) -> AABB<f32> {
     AABB::point(cell_pos.map(|x| x as f32) * tile_size).extend_symmetric(tile_size / 2.0)
 }
@@ -385,3 +824,315 @@ pub fn attack_slots(score: Score) -> usize {
         .map(|(i, _)| i)
         .unwrap_or(SLOTS_REQUIREMENTS.len())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    fn load<T: serde::de::DeserializeOwned>(file: &str) -> T {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("static/rules")
+            .join(file);
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    fn rules() -> Rules {
+        let rules = Rules {
+            enemies: load("enemies.json"),
+            attacks: load("attacks.json"),
+            experience: load("experience.json"),
+            maps: load("maps.json"),
+            waves: load("waves.json"),
+            classes: load("classes.json"),
+            unlocks: load("unlocks.json"),
+        };
+        rules.validate().unwrap();
+        rules
+    }
+
+    fn simulation(rules: &Rules, seed: u64) -> Simulation {
+        Simulation::new(
+            0,
+            seed,
+            &rules.maps.default,
+            &rules.classes.default,
+            &BTreeSet::new(),
+            rules,
+        )
+    }
+
+    /// Places an enemy that stands still this turn, preparing an attack on its own tile.
+    fn place_enemy(simulation: &mut Simulation, typ: &str, position: Position) -> Score {
+        let typ = EnemyType(typ.to_owned());
+        let mut enemy = simulation.spawn_prefabs[&typ].enemy(&typ, position, false);
+        enemy.telegraph = vec![position];
+        let reward = enemy.reward;
+        simulation.enemies.push(enemy);
+        reward
+    }
+
+    #[test]
+    fn tick_moves_the_player() {
+        let rules = rules();
+        let mut simulation = simulation(&rules, 0);
+        simulation.move_time_left = 1.0;
+        simulation.tick(vec2(1, 0));
+        assert_eq!(simulation.player.position, vec2(1, 0));
+        assert_eq!(simulation.move_time_left, simulation.move_time_limit);
+        assert!(!simulation.player.is_dead);
+    }
+
+    #[test]
+    fn tick_kills_enemy_in_attack_range() {
+        let rules = rules();
+        let mut simulation = simulation(&rules, 0);
+        let attack = &mut simulation.player_attacks[0];
+        attack.action.next = 1;
+        let target = attack.attack_positions(vec2(1, 0)).next().unwrap();
+        let reward = place_enemy(&mut simulation, "Attacker", target);
+
+        simulation.tick(vec2(1, 0));
+        assert!(!simulation
+            .enemies
+            .iter()
+            .any(|enemy| enemy.occupies(target)));
+        assert_eq!(simulation.kills[&EnemyType("Attacker".to_owned())], 1);
+        assert_eq!(simulation.score, reward);
+        assert!(!simulation.player.is_dead);
+    }
+
+    #[test]
+    fn tick_kills_player_moving_into_enemy() {
+        let rules = rules();
+        let mut simulation = simulation(&rules, 0);
+        place_enemy(&mut simulation, "Attacker", vec2(1, 0));
+
+        simulation.tick(vec2(1, 0));
+        assert!(simulation.player.is_dead);
+        let position = simulation.player.position;
+        simulation.tick(vec2(0, 1));
+        assert_eq!(simulation.player.position, position);
+    }
+
+    fn place_unit(simulation: &mut Simulation, movement: MovementType, position: Position) -> Id {
+        let typ = EnemyType("Attacker".to_owned());
+        let mut enemy = simulation.spawn_prefabs[&typ].enemy(&typ, position, false);
+        enemy.movement = movement;
+        simulation.enemies.push(enemy);
+        simulation.enemies.len() - 1
+    }
+
+    // The classic map wraps both axes, the player stands on its left edge
+    fn first_step(movement: MovementType, from: Position) -> Option<Position> {
+        let rules = rules();
+        let mut simulation = simulation(&rules, 0);
+        simulation.player.position = vec2(-4, 0);
+        let id = place_unit(&mut simulation, movement, from);
+        simulation.move_request(id).candidates.first().copied()
+    }
+
+    #[test]
+    fn direct_chases_across_edge() {
+        assert_eq!(
+            first_step(MovementType::Direct, vec2(5, 0)),
+            Some(vec2(-4, 0))
+        );
+    }
+
+    #[test]
+    fn knight_jumps_across_edge() {
+        assert_eq!(
+            first_step(MovementType::Knight, vec2(4, 0)),
+            Some(vec2(-4, 1))
+        );
+    }
+
+    #[test]
+    fn bishop_moves_diagonally_across_edge() {
+        assert_eq!(
+            first_step(MovementType::Bishop, vec2(4, 1)),
+            Some(vec2(5, 0))
+        );
+    }
+
+    #[test]
+    fn teleporter_lands_next_to_player_across_edge() {
+        let movement = MovementType::Teleporter {
+            range: 3,
+            charging: true,
+        };
+        assert_eq!(first_step(movement, vec2(4, 0)), Some(vec2(5, 0)));
+    }
+
+    #[test]
+    fn orbiter_approaches_across_edge() {
+        let movement = MovementType::Orbiter {
+            radius: 1,
+            clockwise: false,
+        };
+        assert_eq!(first_step(movement, vec2(3, 0)), Some(vec2(4, 0)));
+    }
+
+    #[test]
+    fn flee_approaches_and_flees_across_edge() {
+        let movement = MovementType::Flee { distance: 3 };
+        assert_eq!(first_step(movement.clone(), vec2(3, 0)), Some(vec2(4, 0)));
+        assert_eq!(first_step(movement, vec2(5, 1)), Some(vec2(4, 2)));
+    }
+
+    // Moves the units towards the player at the origin, while the still enemies wait
+    fn move_units(movement: MovementType, units: &[Position], still: &[Position]) -> Vec<Position> {
+        let rules = rules();
+        let mut simulation = simulation(&rules, 0);
+        for &position in still {
+            place_enemy(&mut simulation, "Attacker", position);
+        }
+        for &position in units {
+            place_unit(&mut simulation, movement.clone(), position);
+        }
+        simulation.move_enemies(false);
+        simulation.enemies[still.len()..]
+            .iter()
+            .map(|enemy| enemy.position)
+            .collect()
+    }
+
+    fn double() -> MovementType {
+        MovementType::SingleDouble {
+            is_next_single: false,
+        }
+    }
+
+    #[test]
+    fn direct_contested_tile() {
+        let units = [vec2(-1, 1), vec2(0, 2)];
+        let expected = [vec2(0, 1), vec2(0, 2)];
+        assert_eq!(move_units(MovementType::Direct, &units, &[]), expected);
+    }
+
+    #[test]
+    fn direct_chain() {
+        let units = [vec2(3, 0), vec2(2, 0)];
+        let expected = [vec2(2, 0), vec2(1, 0)];
+        assert_eq!(move_units(MovementType::Direct, &units, &[]), expected);
+    }
+
+    #[test]
+    fn direct_blocked_waits() {
+        let units = [vec2(2, 0)];
+        let expected = [vec2(2, 0)];
+        assert_eq!(
+            move_units(MovementType::Direct, &units, &[vec2(1, 0)]),
+            expected
+        );
+    }
+
+    #[test]
+    fn direct_sidestep() {
+        let units = [vec2(0, 2), vec2(-1, 1)];
+        let expected = [vec2(0, 1), vec2(-1, 0)];
+        assert_eq!(move_units(MovementType::Direct, &units, &[]), expected);
+    }
+
+    #[test]
+    fn neighbour_contested_tile() {
+        let units = [vec2(2, 0), vec2(2, 1)];
+        let expected = [vec2(1, 0), vec2(1, 1)];
+        assert_eq!(move_units(MovementType::Neighbour, &units, &[]), expected);
+    }
+
+    #[test]
+    fn neighbour_chain() {
+        let units = [vec2(3, 0), vec2(2, 0)];
+        let expected = [vec2(2, 0), vec2(1, 0)];
+        assert_eq!(move_units(MovementType::Neighbour, &units, &[]), expected);
+    }
+
+    #[test]
+    fn neighbour_blocked_waits() {
+        let units = [vec2(2, 0)];
+        let expected = [vec2(2, 0)];
+        assert_eq!(
+            move_units(MovementType::Neighbour, &units, &[vec2(1, 0)]),
+            expected
+        );
+    }
+
+    #[test]
+    fn neighbour_sidestep() {
+        let units = [vec2(2, 2)];
+        let expected = [vec2(1, 2)];
+        assert_eq!(
+            move_units(MovementType::Neighbour, &units, &[vec2(1, 1)]),
+            expected
+        );
+    }
+
+    #[test]
+    fn single_double_contested_tile() {
+        let units = [vec2(-2, 1), vec2(0, 3)];
+        let expected = [vec2(0, 1), vec2(0, 3)];
+        assert_eq!(move_units(double(), &units, &[]), expected);
+    }
+
+    #[test]
+    fn single_double_chain() {
+        let units = [vec2(4, 1), vec2(2, 1)];
+        let expected = [vec2(2, 1), vec2(0, 1)];
+        assert_eq!(move_units(double(), &units, &[]), expected);
+    }
+
+    #[test]
+    fn single_double_blocked_waits() {
+        let units = [vec2(4, 0)];
+        let expected = [vec2(4, 0)];
+        assert_eq!(move_units(double(), &units, &[vec2(2, 0)]), expected);
+    }
+
+    #[test]
+    fn single_double_sidestep() {
+        let units = [vec2(0, 4), vec2(2, 2)];
+        let expected = [vec2(0, 2), vec2(2, 0)];
+        assert_eq!(move_units(double(), &units, &[]), expected);
+    }
+
+    fn play(rules: &Rules, seed: u64) -> String {
+        let mut simulation = simulation(rules, seed);
+        let directions = [vec2(1, 0), vec2(0, 1), vec2(-1, 0), vec2(0, -1)];
+        for turn in 0..200 {
+            if simulation.player.is_dead {
+                break;
+            }
+            if simulation.upgrade_menu.is_some() {
+                simulation.select_upgrade();
+                continue;
+            }
+            simulation.move_time_left = simulation.move_time_limit * (turn % 5) as f32 / 5.0;
+            simulation.tick(directions[turn / 3 % 4]);
+        }
+        format!(
+            "{:?}",
+            (
+                simulation.score,
+                &simulation.player,
+                &simulation.enemies,
+                simulation
+                    .spawns
+                    .iter()
+                    .map(|spawn| (spawn.position, spawn.turns_left))
+                    .collect::<Vec<_>>(),
+                &simulation.kills,
+                simulation.director.turn,
+                simulation.director.budget,
+            )
+        )
+    }
+
+    #[test]
+    fn same_seed_and_commands_replay_identically() {
+        let rules = rules();
+        assert_eq!(play(&rules, 42), play(&rules, 42));
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::HashSet;
+
+use super::*;
+
+pub struct MoveRequest {
+    pub id: Id,
+    pub from: Position,
+    /// Tiles the unit wants to move to, from the most preferred one.
+    /// If none of them are available the unit waits in place.
+    pub candidates: Vec<Position>,
+    /// Lower values move first.
+    pub priority: (Coord, Coord),
+}
+
+/// Decides where every unit ends up this turn, so that no two units share a tile.
+/// A unit's current tile stays occupied until that unit has moved away.
+pub fn resolve_moves(requests: Vec<MoveRequest>) -> Vec<(Id, Position)> {
+    let mut reserved = HashSet::new();
+    let mut resolved = Vec::new();
+
+    let (waiting, mut pending): (Vec<_>, Vec<_>) = requests
+        .into_iter()
+        .partition(|request| request.candidates.is_empty());
+    for request in waiting {
+        reserved.insert(request.from);
+        resolved.push((request.id, request.from));
+    }
+    pending.sort_by_key(|request| (request.priority, request.id));
+
+    while !pending.is_empty() {
+        let mut progress = false;
+        let mut i = 0;
+        while i < pending.len() {
+            let request = &pending[i];
+            let is_occupied = |pos: Position| {
+                pending
+                    .iter()
+                    .enumerate()
+                    .any(|(j, other)| j != i && other.from == pos)
+            };
+            let mut target = None;
+            for &pos in &request.candidates {
+                if reserved.contains(&pos) {
+                    // Try sidestepping
+                    continue;
+                }
+                if !is_occupied(pos) {
+                    target = Some(pos);
+                }
+                // Either move there, or wait until the tile is free
+                break;
+            }
+            match target {
+                Some(pos) => {
+                    reserved.insert(pos);
+                    resolved.push((request.id, pos));
+                    pending.remove(i);
+                    progress = true;
+                }
+                None if request.candidates.iter().all(|pos| reserved.contains(pos)) => {
+                    // Nowhere to go
+                    reserved.insert(request.from);
+                    resolved.push((request.id, request.from));
+                    pending.remove(i);
+                    progress = true;
+                }
+                None => i += 1,
+            }
+        }
+        if !progress {
+            // Units are blocking each other, the most important one waits
+            let request = pending.remove(0);
+            reserved.insert(request.from);
+            resolved.push((request.id, request.from));
+        }
+    }
+
+    resolved
+}
@@ -0,0 +1,131 @@
+use std::collections::{HashSet, VecDeque};
+
+use super::*;
+
+pub fn wrapped_delta(from: Position, to: Position, arena: &Arena) -> Position {
+    let bounds = arena.bounds;
+    vec2(
+        if arena.wrapping.x {
+            wrapped_delta_coord(from.x, to.x, bounds.x_max - bounds.x_min + 1)
+        } else {
+            to.x - from.x
+        },
+        if arena.wrapping.y {
+            wrapped_delta_coord(from.y, to.y, bounds.y_max - bounds.y_min + 1)
+        } else {
+            to.y - from.y
+        },
+    )
+}
+
+fn wrapped_delta_coord(from: Coord, to: Coord, width: Coord) -> Coord {
+    let delta = (to - from).rem_euclid(width);
+    if delta * 2 > width {
+        delta - width
+    } else {
+        delta
+    }
+}
+
+pub fn wrapped_distance(from: Position, to: Position, arena: &Arena) -> Coord {
+    let delta = wrapped_delta(from, to, arena);
+    delta.x.abs() + delta.y.abs()
+}
+
+/// Returns `None` if the target cannot be reached.
+pub fn next_step(
+    from: Position,
+    target: Position,
+    movement: &MovementType,
+    arena: &Arena,
+    is_blocked: impl Fn(Position) -> bool,
+) -> Option<Position> {
+    if from == target {
+        return None;
+    }
+
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    visited.insert((from, movement.clone()));
+    queue.push_back((from, movement.clone(), None));
+    while let Some((pos, movement, first_step)) = queue.pop_front() {
+        for delta in movement.steps() {
+            let next = match arena.step(pos, delta) {
+                Some((next, _)) => next,
+                None => continue,
+            };
+            let first_step = first_step.unwrap_or(next);
+            if next == target {
+                return Some(first_step);
+            }
+            if !arena.is_walkable(next) || is_blocked(next) {
+                continue;
+            }
+            let movement = movement.advanced();
+            if visited.insert((next, movement.clone())) {
+                queue.push_back((next, movement, Some(first_step)));
+            }
+        }
+    }
+    None
+}
+
+/// Steps towards the target along the shorter way around the arena,
+/// for units that do not search for a path.
+pub fn wrapped_step(
+    movement: &mut MovementType,
+    from: Position,
+    target: Position,
+    arena: &Arena,
+) -> Option<Position> {
+    let delta = movement.move_towards(wrapped_delta(from, target, arena));
+    arena.step(from, delta).map(|(pos, _)| pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn towards(movement: &mut MovementType, from: Position, target: Position) -> Position {
+        let arena = Arena::new(AABB::from_corners(vec2(0, 0), vec2(9, 9)));
+        movement.move_towards(wrapped_delta(from, target, &arena))
+    }
+
+    #[test]
+    fn knight_jumps_across_edge() {
+        let mut movement = MovementType::Knight;
+        assert_eq!(towards(&mut movement, vec2(9, 5), vec2(1, 5)), vec2(2, 1));
+    }
+
+    #[test]
+    fn bishop_moves_diagonally_across_corner() {
+        let mut movement = MovementType::Bishop;
+        assert_eq!(towards(&mut movement, vec2(0, 0), vec2(8, 9)), vec2(-1, -1));
+    }
+
+    #[test]
+    fn teleporter_charges_then_lands_next_to_target_across_edge() {
+        let mut movement = MovementType::Teleporter {
+            range: 3,
+            charging: false,
+        };
+        assert_eq!(towards(&mut movement, vec2(8, 5), vec2(1, 5)), vec2(0, 0));
+        assert_eq!(towards(&mut movement, vec2(8, 5), vec2(1, 5)), vec2(2, 0));
+    }
+
+    #[test]
+    fn orbiter_approaches_across_edge() {
+        let mut movement = MovementType::Orbiter {
+            radius: 1,
+            clockwise: false,
+        };
+        assert_eq!(towards(&mut movement, vec2(9, 5), vec2(1, 5)), vec2(1, 0));
+    }
+
+    #[test]
+    fn flee_approaches_and_flees_across_edge() {
+        let mut movement = MovementType::Flee { distance: 3 };
+        assert_eq!(towards(&mut movement, vec2(9, 5), vec2(3, 5)), vec2(1, 0));
+        assert_eq!(towards(&mut movement, vec2(0, 5), vec2(2, 5)), vec2(-1, 0));
+    }
+}
@@ -1,5 +1,8 @@
 use geng::prelude::*;
+use std::cell::RefCell;
 
+mod audio;
+mod input;
 mod logic;
 mod model;
 mod renderer;
@@ -14,13 +17,64 @@ pub struct Assets {
     pub blip: geng::Sound,
     pub select: geng::Sound,
     pub upgrade: geng::Sound,
+    pub rules: model::Rules,
     // pub music: geng::Sound,
+    // pub menu_music: geng::Sound,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Opt {
+    pub seed: Option<u64>,
+    pub map: Option<String>,
+    pub class: Option<String>,
+    pub replay: Option<std::path::PathBuf>,
+    pub replay_speed: Option<f32>,
+}
+
+impl Opt {
+    pub fn parse() -> Self {
+        let mut opt = Self::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => {
+                    let seed = args.next().expect("Expected a seed after --seed");
+                    opt.seed = Some(seed.parse().expect("Seed must be a non-negative integer"));
+                }
+                "--map" => {
+                    let map = args.next().expect("Expected a map name after --map");
+                    opt.map = Some(map);
+                }
+                "--class" => {
+                    let class = args.next().expect("Expected a class name after --class");
+                    opt.class = Some(class);
+                }
+                "--replay" => {
+                    let path = args.next().expect("Expected a path after --replay");
+                    opt.replay = Some(path.into());
+                }
+                "--replay-speed" => {
+                    let speed = args.next().expect("Expected a speed after --replay-speed");
+                    opt.replay_speed = Some(speed.parse().expect("Replay speed must be a number"));
+                }
+                _ => panic!("Unknown argument: {}", arg),
+            }
+        }
+        opt
+    }
 }
 
 fn main() {
     logger::init().unwrap();
     geng::setup_panic_handler();
 
+    let opt = Opt::parse();
+    let seed = opt.seed.unwrap_or_else(|| global_rng().gen());
+    let replay = opt.replay.as_ref().map(|path| {
+        model::Replay::load(path)
+            .unwrap_or_else(|error| panic!("Failed to load replay {:?}: {}", path, error))
+    });
+
     let geng = Geng::new("Delay the inevitable");
     let assets = <Assets as geng::LoadAsset>::load(&geng, &static_path());
 
@@ -30,10 +84,57 @@ fn main() {
             let geng = geng.clone();
             move |assets| {
                 let mut assets = assets.unwrap();
+                if let Err(error) = assets.rules.validate() {
+                    panic!("{}", error);
+                }
                 assets.lock.set_filter(ugli::Filter::Nearest);
                 assets.exclamation.set_filter(ugli::Filter::Nearest);
                 // assets.music.looped = true;
-                model::GameState::new(&geng, &Rc::new(assets))
+                // assets.menu_music.looped = true;
+                let assets = Rc::new(assets);
+                let shared = model::Shared {
+                    audio: Rc::new(audio::Audio::new(&assets)),
+                    gamepad: Rc::new(RefCell::new(input::Gamepad::default())),
+                    saves: Rc::new(RefCell::new(model::Saves::load(&assets.rules))),
+                };
+                let state: Box<dyn geng::State> = match replay {
+                    Some(replay) => Box::new(model::GameState::new_replay(
+                        &geng,
+                        &assets,
+                        replay,
+                        opt.replay_speed.unwrap_or(1.0),
+                        shared,
+                    )),
+                    None => {
+                        let map = opt.map.as_ref().unwrap_or(&assets.rules.maps.default);
+                        if assets.rules.maps.get(map).is_none() {
+                            panic!("Unknown map {:?}", map);
+                        }
+                        match &opt.class {
+                            Some(class) => {
+                                if assets.rules.classes.get(class).is_none() {
+                                    panic!("Unknown class {:?}", class);
+                                }
+                                let content = model::Content::Class(class.clone());
+                                if !shared
+                                    .saves
+                                    .borrow()
+                                    .profile
+                                    .is_unlocked(&content, &assets.rules)
+                                {
+                                    panic!("Class {:?} is locked", class);
+                                }
+                                Box::new(model::GameState::new(
+                                    &geng, &assets, seed, map, class, shared,
+                                ))
+                            }
+                            None => {
+                                Box::new(model::TitleScreen::new(&geng, &assets, seed, map, shared))
+                            }
+                        }
+                    }
+                };
+                state
             }
         }),
     );
@@ -0,0 +1,82 @@
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Tile {
+    Floor,
+    Wall,
+    /// Kills the player, enemies avoid it.
+    Pit,
+    /// Units that step on it skip their next move.
+    Slow,
+    /// Units cannot wrap around the arena from this tile.
+    Barrier,
+}
+
+#[derive(Debug, Clone)]
+pub struct Arena {
+    pub bounds: AABB<Coord>,
+    pub wrapping: Vec2<bool>,
+    pub tiles: HashMap<Position, Tile>,
+    pub spawns: Vec<(Position, u32)>,
+}
+
+impl Arena {
+    pub fn new(bounds: AABB<Coord>) -> Self {
+        Self {
+            bounds,
+            wrapping: vec2(true, true),
+            tiles: HashMap::new(),
+            spawns: bounds.corners().into_iter().map(|pos| (pos, 1)).collect(),
+        }
+    }
+
+    pub fn contains(&self, pos: Position) -> bool {
+        (self.bounds.x_min..=self.bounds.x_max).contains(&pos.x)
+            && (self.bounds.y_min..=self.bounds.y_max).contains(&pos.y)
+    }
+
+    pub fn tile(&self, pos: Position) -> Tile {
+        self.tiles.get(&pos).copied().unwrap_or(Tile::Floor)
+    }
+
+    pub fn is_walkable(&self, pos: Position) -> bool {
+        !matches!(self.tile(pos), Tile::Wall | Tile::Pit)
+    }
+
+    /// Returns the wrapped position and whether it wrapped,
+    /// or `None` if it is outside along an axis that does not wrap.
+    pub fn wrap(&self, pos: Position) -> Option<(Position, bool)> {
+        let wrap_coord = |pos: Coord, wrapping: bool, min: Coord, max: Coord| {
+            if wrapping {
+                Some(logic::wrap_coord(pos, vec2(min, max)))
+            } else if (min..=max).contains(&pos) {
+                Some((pos, false))
+            } else {
+                None
+            }
+        };
+        let (x, jump_x) = wrap_coord(pos.x, self.wrapping.x, self.bounds.x_min, self.bounds.x_max)?;
+        let (y, jump_y) = wrap_coord(pos.y, self.wrapping.y, self.bounds.y_min, self.bounds.y_max)?;
+        Some((vec2(x, y), jump_x || jump_y))
+    }
+
+    /// Returns the new position and whether it wrapped,
+    /// or `None` if the move leaves the arena or the wrap is blocked by a barrier.
+    pub fn step(&self, from: Position, delta: Position) -> Option<(Position, bool)> {
+        let (pos, jump) = self.wrap(from + delta)?;
+        if jump && self.tile(from) == Tile::Barrier {
+            return None;
+        }
+        Some((pos, jump))
+    }
+
+    pub fn line_of_sight(&self, from: Position, to: Position) -> bool {
+        let delta = to - from;
+        let steps = delta.x.abs().max(delta.y.abs());
+        (1..=steps).all(|step| {
+            let t = step as f32 / steps as f32;
+            let pos = from + delta.map(|x| (x as f32 * t).round() as Coord);
+            self.tile(pos) != Tile::Wall
+        })
+    }
+}
@@ -0,0 +1,228 @@
+use super::*;
+
+pub struct ClassSelect {
+    geng: Geng,
+    assets: Rc<Assets>,
+    seed: u64,
+    map: String,
+    choice: usize,
+    shared: Shared,
+    camera: Camera2d,
+    transition: Option<geng::Transition>,
+}
+
+impl ClassSelect {
+    pub fn new(geng: &Geng, assets: &Rc<Assets>, seed: u64, map: &str, shared: Shared) -> Self {
+        shared.audio.play_music(MusicTrack::Menu);
+        let classes = &assets.rules.classes;
+        Self {
+            geng: geng.clone(),
+            assets: assets.clone(),
+            seed,
+            map: map.to_owned(),
+            choice: classes
+                .classes
+                .iter()
+                .position(|class| class.name == classes.default)
+                .unwrap_or(0),
+            shared,
+            camera: Camera2d {
+                center: Vec2::ZERO,
+                rotation: 0.0,
+                fov: 1080.0,
+            },
+            transition: None,
+        }
+    }
+
+    fn change_choice(&mut self, delta: isize) {
+        let count = self.assets.rules.classes.classes.len() as isize;
+        self.choice = (self.choice as isize + delta).rem_euclid(count) as usize;
+        self.shared.audio.play(SoundType::Blip);
+    }
+
+    fn select(&mut self) {
+        let rules = &self.assets.rules;
+        let class = &rules.classes.classes[self.choice];
+        let content = Content::Class(class.name.clone());
+        if !self
+            .shared
+            .saves
+            .borrow()
+            .profile
+            .is_unlocked(&content, rules)
+        {
+            let bought = self.shared.saves.borrow_mut().profile.buy(&content, rules);
+            self.shared.audio.play(if bought {
+                SoundType::Upgrade
+            } else {
+                SoundType::Death
+            });
+            return;
+        }
+
+        let state = GameState::new(
+            &self.geng,
+            &self.assets,
+            self.seed,
+            &self.map,
+            &class.name,
+            self.shared.clone(),
+        );
+        self.shared.audio.play(SoundType::Select);
+        self.transition = Some(geng::Transition::Switch(Box::new(state)));
+    }
+
+    fn handle_input(&mut self, input: Input) {
+        if self.transition.is_some() {
+            return;
+        }
+        let controls = self.shared.saves.borrow().settings.controls.clone();
+        if let Some(direction) = controls.menu_direction(input) {
+            if direction.x != 0 {
+                self.change_choice(direction.x as isize);
+            }
+        } else if controls.is_confirm(input) {
+            self.select();
+        }
+    }
+}
+
+impl geng::State for ClassSelect {
+    fn update(&mut self, delta_time: f64) {
+        self.shared.audio.update(delta_time as f32);
+        let inputs = self.shared.gamepad.borrow_mut().poll();
+        for input in inputs {
+            self.handle_input(input);
+        }
+    }
+
+    fn draw(&mut self, framebuffer: &mut ugli::Framebuffer) {
+        ugli::clear(framebuffer, Some(Color::BLACK), None);
+        let framebuffer_size = framebuffer.size().map(|x| x as f32);
+        let framebuffer_size = vec2(
+            self.camera.fov / framebuffer_size.y * framebuffer_size.x,
+            self.camera.fov,
+        );
+        self.camera.center = framebuffer_size / 2.0;
+        let mut renderer = Renderer::new(&self.geng, &self.assets, &self.camera, framebuffer);
+
+        renderer.draw_text(
+            "Choose your class",
+            vec2(framebuffer_size.x / 2.0, framebuffer_size.y - 100.0),
+            vec2(0.5, 1.0),
+            60.0,
+            Color::WHITE,
+        );
+
+        let saves = self.shared.saves.borrow();
+        let profile = &saves.profile;
+        let rules = &self.assets.rules;
+        renderer.draw_text(
+            &format!("Coins: {}", profile.currency),
+            vec2(10.0, framebuffer_size.y - 10.0),
+            vec2(0.0, 1.0),
+            30.0,
+            Color::GRAY,
+        );
+
+        let classes = &rules.classes.classes;
+        let cards_width =
+            classes.len() as f32 * (CLASS_CARD_SIZE.x + UPGRADE_EXTRA_SPACE) - UPGRADE_EXTRA_SPACE;
+        let card_aabb = AABB::ZERO.extend_symmetric(CLASS_CARD_SIZE / 2.0);
+        let left_pos = framebuffer_size / 2.0 - vec2((cards_width - CLASS_CARD_SIZE.x) / 2.0, 0.0);
+        for (i, class) in classes.iter().enumerate() {
+            let aabb = card_aabb.translate(
+                left_pos + i as f32 * vec2(CLASS_CARD_SIZE.x + UPGRADE_EXTRA_SPACE, 0.0),
+            );
+            renderer.draw_aabb_frame(aabb, UPGRADE_FRAME_WIDTH, UPGRADE_FRAME_COLOR);
+            if i == self.choice {
+                renderer.draw_aabb(
+                    aabb.extend_uniform(-UPGRADE_FRAME_WIDTH / 2.0),
+                    UPGRADE_SELECTED_COLOR,
+                );
+            }
+            let aabb = aabb.extend_uniform(-CLASS_CARD_SIZE.x * 0.1);
+            let content = Content::Class(class.name.clone());
+
+            let line_height = aabb.height() / 8.0;
+            let line = |i: usize| {
+                AABB::from_corners(
+                    vec2(aabb.x_min, aabb.y_max - line_height * (i + 1) as f32),
+                    vec2(aabb.x_max, aabb.y_max - line_height * i as f32),
+                )
+            };
+            renderer.draw_text_fit(&class.name, line(0), UPGRADE_TEXT_COLOR);
+
+            let attack_aabb = AABB::from_corners(line(3).bottom_left(), line(1).top_right())
+                .extend_uniform(-line_height * 0.25);
+            if let Some(unlock) = rules
+                .unlocks
+                .get(&content)
+                .filter(|_| !profile.is_unlocked(&content, rules))
+            {
+                renderer.draw_texture(
+                    &self.assets.lock,
+                    AABB::point(attack_aabb.center())
+                        .extend_uniform(attack_aabb.width().min(attack_aabb.height()) / 2.0),
+                );
+                let texts = [
+                    unlock.requirement.description(),
+                    if unlock.cost > 0 {
+                        format!("Cost: {} coins", unlock.cost)
+                    } else {
+                        String::new()
+                    },
+                ];
+                for (j, text) in texts.iter().enumerate() {
+                    renderer.draw_text_fit(text, line(4 + j), ATTACK_LOCK_TEXT_COLOR);
+                }
+                continue;
+            }
+
+            // Starting attack
+            if let Some(id) = class.attacks.first() {
+                renderer.draw_attack(&rules.attacks.create(id), attack_aabb);
+            }
+
+            let texts = [
+                Ultimate::new(class.ultimate).name().to_owned(),
+                format!("Timer: {:.0} sec", class.move_time),
+                format!("Slots: {}", class.attack_slots),
+                format!("Best: {}", profile.highscore(&class.name)),
+            ];
+            for (j, text) in texts.iter().enumerate() {
+                renderer.draw_text_fit(text, line(4 + j), UPGRADE_TEXT_COLOR);
+            }
+        }
+
+        let class = &classes[self.choice];
+        renderer.draw_text(
+            &class.description,
+            vec2(
+                framebuffer_size.x / 2.0,
+                framebuffer_size.y / 2.0 - CLASS_CARD_SIZE.y / 2.0 - 50.0,
+            ),
+            vec2(0.5, 1.0),
+            30.0,
+            Color::GRAY,
+        );
+        renderer.draw_text(
+            "(Enter)",
+            vec2(framebuffer_size.x / 2.0, 100.0),
+            vec2(0.5, 0.0),
+            30.0,
+            Color::WHITE,
+        );
+    }
+
+    fn handle_event(&mut self, event: geng::Event) {
+        if let geng::Event::KeyDown { key } = event {
+            self.handle_input(Input::Key(key));
+        }
+    }
+
+    fn transition(&mut self) -> Option<geng::Transition> {
+        self.transition.take()
+    }
+}
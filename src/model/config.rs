@@ -0,0 +1,991 @@
+use super::*;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(geng::Assets, Debug, Clone)]
+pub struct Rules {
+    #[asset(path = "enemies.json")]
+    pub enemies: EnemyConfig,
+    #[asset(path = "attacks.json")]
+    pub attacks: AttackLibrary,
+    #[asset(path = "experience.json")]
+    pub experience: ExperienceCurve,
+    #[asset(path = "maps.json")]
+    pub maps: MapLibrary,
+    #[asset(path = "waves.json")]
+    pub waves: WaveConfig,
+    #[asset(path = "classes.json")]
+    pub classes: ClassLibrary,
+    #[asset(path = "unlocks.json")]
+    pub unlocks: UnlockConfig,
+}
+
+#[derive(geng::Assets, Debug, Clone, Serialize, Deserialize)]
+#[asset(json)]
+pub struct EnemyConfig {
+    pub enemies: BTreeMap<EnemyType, EnemyDefinition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnemyDefinition {
+    pub movement: MovementType,
+    /// Search for a path around walls and other enemies instead of stepping straight at the player.
+    #[serde(default)]
+    pub pathfinding: bool,
+    pub color: Color<f32>,
+    pub min_score: Score,
+    #[serde(default)]
+    pub attack: Option<AttackId>,
+    #[serde(default = "default_health")]
+    pub health: Health,
+    /// Ignore the first hit each turn.
+    #[serde(default)]
+    pub armored: bool,
+    #[serde(default = "default_size")]
+    pub size: Coord,
+    #[serde(default = "default_reward")]
+    pub reward: Score,
+    /// Phases ordered by decreasing health.
+    #[serde(default)]
+    pub phases: Vec<PhaseDefinition>,
+    #[serde(default)]
+    pub minions: Option<Minions>,
+    /// Enemies without a cost are never bought by the director and only come from waves.
+    #[serde(default)]
+    pub cost: Option<f32>,
+    /// Number of turns the spawn is telegraphed for.
+    #[serde(default = "default_telegraph")]
+    pub telegraph: Time,
+    #[serde(default)]
+    pub spawn: SpawnStrategy,
+    /// What happens when the player stands on the spawn point.
+    #[serde(default)]
+    pub blocked: BlockedSpawn,
+}
+
+fn default_health() -> Health {
+    1
+}
+
+fn default_size() -> Coord {
+    1
+}
+
+fn default_reward() -> Score {
+    1
+}
+
+/// Takes effect once the enemy's health drops to `health`.
+/// Omitted fields keep the behaviour of the previous phase.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhaseDefinition {
+    pub health: Health,
+    #[serde(default)]
+    pub color: Option<Color<f32>>,
+    #[serde(default)]
+    pub movement: Option<MovementType>,
+    #[serde(default)]
+    pub attack: Option<AttackId>,
+    #[serde(default)]
+    pub minions: Option<Minions>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Minions {
+    pub enemy: EnemyType,
+    pub count: usize,
+    pub every: Time,
+    #[serde(default)]
+    pub from: Option<SpawnStrategy>,
+}
+
+fn default_telegraph() -> Time {
+    1
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpawnStrategy {
+    MapSpawns,
+    Corners,
+    Edges,
+    North,
+    South,
+    East,
+    West,
+    /// The tiles farthest away from the player.
+    Farthest,
+    /// The tiles at the given distance from the player.
+    Ring {
+        distance: Coord,
+    },
+}
+
+impl Default for SpawnStrategy {
+    fn default() -> Self {
+        Self::MapSpawns
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlockedSpawn {
+    /// Wait until the player leaves the tile.
+    Delay,
+    /// Pick another spawn point.
+    Relocate,
+    /// Spawn anyway, killing the player.
+    Crush,
+}
+
+impl Default for BlockedSpawn {
+    fn default() -> Self {
+        Self::Delay
+    }
+}
+
+#[derive(geng::Assets, Debug, Clone, Serialize, Deserialize)]
+#[asset(json)]
+pub struct AttackLibrary {
+    pub attacks: Vec<AttackDefinition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttackDefinition {
+    pub id: AttackId,
+    pub name: String,
+    pub levels: Vec<AttackLevel>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttackLevel {
+    pub cooldown: Time,
+    #[serde(default = "default_damage")]
+    pub damage: Health,
+    pub pattern: PatternDefinition,
+}
+
+fn default_damage() -> Health {
+    1
+}
+
+/// Either a list of tile offsets, or ASCII art where `P` is the caster,
+/// `X` is an attacked tile and `.` is an empty tile. The caster faces right.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PatternDefinition {
+    Tiles(Vec<[Coord; 2]>),
+    Ascii(Vec<String>),
+}
+
+#[derive(geng::Assets, Debug, Clone, Serialize, Deserialize)]
+#[asset(json)]
+pub struct ExperienceCurve {
+    #[serde(default)]
+    pub table: Vec<Score>,
+    pub extension: CurveExtension,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CurveExtension {
+    /// Every level past the table needs `step` more experience than the previous one did.
+    Arithmetic { step: Score },
+    /// The requirement is `base + linear * level + quadratic * level^2`.
+    Polynomial {
+        base: f32,
+        linear: f32,
+        quadratic: f32,
+    },
+}
+
+#[derive(geng::Assets, Debug, Clone, Serialize, Deserialize)]
+#[asset(json)]
+pub struct MapLibrary {
+    pub default: String,
+    pub maps: Vec<MapDefinition>,
+}
+
+/// `layout` is ASCII art with the first row at the top: `.` is floor, `#` a wall,
+/// `O` a pit, `~` a slow tile, `|` a barrier and `P` the player start.
+/// Digits are spawn points, the digit being the weight of the spawn point.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MapDefinition {
+    pub name: String,
+    #[serde(default = "default_wrap")]
+    pub wrap_x: bool,
+    #[serde(default = "default_wrap")]
+    pub wrap_y: bool,
+    pub layout: Vec<String>,
+}
+
+fn default_wrap() -> bool {
+    true
+}
+
+#[derive(geng::Assets, Debug, Clone, Serialize, Deserialize)]
+#[asset(json)]
+pub struct ClassLibrary {
+    /// The class whose high score is kept in `highscore.json`.
+    pub default: String,
+    pub classes: Vec<ClassDefinition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClassDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub attacks: Vec<AttackId>,
+    /// Attacks the class can learn from upgrades.
+    pub potential: Vec<AttackId>,
+    pub ultimate: UltimateType,
+    /// Seconds the player has to make each move.
+    pub move_time: f32,
+    /// Maximum number of attack slots, unlocked by the high score.
+    pub attack_slots: usize,
+    pub upgrades: Vec<UpgradeType>,
+}
+
+#[derive(geng::Assets, Debug, Clone, Serialize, Deserialize)]
+#[asset(json)]
+pub struct UnlockConfig {
+    /// Currency earned per point scored in a run.
+    pub currency_per_score: f32,
+    /// Content not listed here is available from the start.
+    pub unlocks: Vec<UnlockDefinition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnlockDefinition {
+    pub content: Content,
+    pub requirement: Achievement,
+    /// Currency to spend once the requirement is met. Only classes can cost currency,
+    /// everything else is unlocked as soon as the requirement is met.
+    #[serde(default)]
+    pub cost: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Content {
+    Attack(AttackId),
+    Enemy(EnemyType),
+    Class(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Achievement {
+    BestScore(Score),
+    BestLevel(u32),
+    Runs(u32),
+    TotalKills(u32),
+    Kills { enemy: EnemyType, count: u32 },
+}
+
+#[derive(geng::Assets, Debug, Clone, Serialize, Deserialize)]
+#[asset(json)]
+pub struct WaveConfig {
+    pub pacing: Pacing,
+    pub elites: EliteConfig,
+    /// Scripted waves, ordered by turn.
+    pub waves: Vec<WaveDefinition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pacing {
+    pub initial_budget: f32,
+    pub budget_per_turn: f32,
+    pub max_budget: f32,
+    /// Extra gain per point of score, relative to `budget_per_turn`.
+    pub score_scaling: f32,
+    /// The gain is reduced by this fraction for every enemy alive.
+    pub enemy_pressure: f32,
+    /// The gain is reduced by this fraction of the move time the player used up.
+    pub time_pressure: f32,
+}
+
+/// Enemies roll each modifier with a chance that grows with the score.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EliteConfig {
+    pub chance_per_score: f32,
+    pub max_chance: f32,
+    pub max_modifiers: usize,
+    pub modifiers: Vec<Modifier>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Modifier {
+    /// Ignores the first hit.
+    Shielded,
+    /// Moves twice per turn.
+    Hasty,
+    /// Splits into two weaker copies when killed, only rolled for single-tile enemies.
+    Splitting,
+    /// Damages the neighbouring tiles when killed.
+    Explosive,
+    /// Has an extra hit point and slowly heals.
+    Regenerating,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WaveDefinition {
+    pub turn: Time,
+    /// Boss waves pause the adaptive spawns until all of their enemies are dead.
+    #[serde(default)]
+    pub boss: bool,
+    pub groups: Vec<WaveGroup>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WaveGroup {
+    pub enemy: EnemyType,
+    pub count: usize,
+    #[serde(default)]
+    pub from: Option<SpawnStrategy>,
+}
+
+impl Rules {
+    pub fn validate(&self) -> Result<(), String> {
+        self.attacks
+            .validate()
+            .map_err(|error| format!("Invalid attacks: {}", error))?;
+        self.enemies
+            .validate(&self.attacks)
+            .map_err(|error| format!("Invalid enemies: {}", error))?;
+        self.experience
+            .validate()
+            .map_err(|error| format!("Invalid experience curve: {}", error))?;
+        self.maps
+            .validate()
+            .map_err(|error| format!("Invalid maps: {}", error))?;
+        self.waves
+            .validate(&self.enemies)
+            .map_err(|error| format!("Invalid waves: {}", error))?;
+        self.classes
+            .validate(&self.attacks)
+            .map_err(|error| format!("Invalid classes: {}", error))?;
+        self.unlocks
+            .validate(self)
+            .map_err(|error| format!("Invalid unlocks: {}", error))?;
+        Ok(())
+    }
+}
+
+impl EnemyConfig {
+    pub fn validate(&self, attacks: &AttackLibrary) -> Result<(), String> {
+        if self.enemies.is_empty() {
+            return Err("no enemies are defined".to_owned());
+        }
+        if !self
+            .enemies
+            .values()
+            .any(|definition| definition.min_score == 0)
+        {
+            return Err("at least one enemy must have a min_score of 0".to_owned());
+        }
+        for (typ, definition) in &self.enemies {
+            definition
+                .validate(self, attacks)
+                .map_err(|error| format!("enemy {:?}: {}", typ.0, error))?;
+        }
+        Ok(())
+    }
+}
+
+impl EnemyDefinition {
+    fn validate(&self, enemies: &EnemyConfig, attacks: &AttackLibrary) -> Result<(), String> {
+        let color = self.color;
+        if [color.r, color.g, color.b, color.a]
+            .iter()
+            .any(|c| !(0.0..=1.0).contains(c))
+        {
+            return Err("color components must be in range 0..=1".to_owned());
+        }
+        if self.health == 0 {
+            return Err("health must be positive".to_owned());
+        }
+        if self.cost.is_some_and(|cost| cost <= 0.0) {
+            return Err("cost must be positive".to_owned());
+        }
+        if self.telegraph < 1 {
+            return Err("telegraph must be at least 1 turn".to_owned());
+        }
+        self.spawn.validate()?;
+        if self.size < 1 {
+            return Err("size must be at least 1".to_owned());
+        }
+        if self.size > 1 && self.pathfinding {
+            return Err("large enemies cannot use pathfinding".to_owned());
+        }
+        if self.pathfinding && !self.movement.approaches() {
+            return Err("only approaching movement can use pathfinding".to_owned());
+        }
+        self.movement.validate()?;
+        let validate_attack = |attack: &Option<AttackId>| match attack {
+            Some(attack) if attacks.get(attack).is_none() => {
+                Err(format!("unknown attack {:?}", attack.0))
+            }
+            _ => Ok(()),
+        };
+        validate_attack(&self.attack)?;
+        if let Some(minions) = &self.minions {
+            minions.validate(enemies)?;
+        }
+        let mut health = self.health;
+        for (i, phase) in self.phases.iter().enumerate() {
+            if phase.health == 0 || phase.health >= health {
+                return Err(format!(
+                    "phase {}: health must be positive and below the previous phase",
+                    i + 1
+                ));
+            }
+            health = phase.health;
+            if let Some(movement) = &phase.movement {
+                movement
+                    .validate()
+                    .map_err(|error| format!("phase {}: {}", i + 1, error))?;
+            }
+            validate_attack(&phase.attack)
+                .map_err(|error| format!("phase {}: {}", i + 1, error))?;
+            if let Some(minions) = &phase.minions {
+                minions
+                    .validate(enemies)
+                    .map_err(|error| format!("phase {}: {}", i + 1, error))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MovementType {
+    fn validate(&self) -> Result<(), String> {
+        match *self {
+            Self::Teleporter { range, .. } if range < 1 => {
+                Err("teleport range must be at least 1".to_owned())
+            }
+            Self::Orbiter { radius, .. } if radius < 1 => {
+                Err("orbit radius must be at least 1".to_owned())
+            }
+            Self::Flee { distance } if distance < 1 => {
+                Err("flee distance must be at least 1".to_owned())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Minions {
+    fn validate(&self, enemies: &EnemyConfig) -> Result<(), String> {
+        if !enemies.enemies.contains_key(&self.enemy) {
+            return Err(format!("unknown minion {:?}", self.enemy.0));
+        }
+        if self.count == 0 || self.every < 1 {
+            return Err("minion count and interval must be positive".to_owned());
+        }
+        if let Some(from) = self.from {
+            from.validate()?;
+        }
+        Ok(())
+    }
+}
+
+impl SpawnStrategy {
+    fn validate(&self) -> Result<(), String> {
+        match *self {
+            Self::Ring { distance } if distance < 1 => {
+                Err("ring distance must be at least 1".to_owned())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl UnlockConfig {
+    pub fn get(&self, content: &Content) -> Option<&UnlockDefinition> {
+        self.unlocks
+            .iter()
+            .find(|unlock| unlock.content == *content)
+    }
+
+    pub fn validate(&self, rules: &Rules) -> Result<(), String> {
+        if self.currency_per_score < 0.0 {
+            return Err("currency per score must not be negative".to_owned());
+        }
+        for (i, unlock) in self.unlocks.iter().enumerate() {
+            if self.unlocks[..i]
+                .iter()
+                .any(|other| other.content == unlock.content)
+            {
+                return Err(format!("{:?} is unlocked more than once", unlock.content));
+            }
+            unlock
+                .validate(rules)
+                .map_err(|error| format!("{:?}: {}", unlock.content, error))?;
+        }
+        let is_locked = |content: Content| self.get(&content).is_some();
+        if !rules.enemies.enemies.iter().any(|(typ, definition)| {
+            definition.min_score == 0 && !is_locked(Content::Enemy(typ.clone()))
+        }) {
+            return Err(
+                "an enemy with a min_score of 0 must be available from the start".to_owned(),
+            );
+        }
+        Ok(())
+    }
+}
+
+impl UnlockDefinition {
+    fn validate(&self, rules: &Rules) -> Result<(), String> {
+        match &self.content {
+            Content::Attack(id) => {
+                if rules.attacks.get(id).is_none() {
+                    return Err("unknown attack".to_owned());
+                }
+                if rules
+                    .classes
+                    .classes
+                    .iter()
+                    .any(|class| class.attacks.contains(id))
+                {
+                    return Err("starting attacks cannot be locked".to_owned());
+                }
+            }
+            Content::Enemy(typ) => {
+                if !rules.enemies.enemies.contains_key(typ) {
+                    return Err("unknown enemy".to_owned());
+                }
+            }
+            Content::Class(name) => {
+                if rules.classes.get(name).is_none() {
+                    return Err("unknown class".to_owned());
+                }
+                if *name == rules.classes.default {
+                    return Err("the default class cannot be locked".to_owned());
+                }
+            }
+        }
+        if self.cost > 0 && !matches!(self.content, Content::Class(_)) {
+            return Err("only classes can cost currency".to_owned());
+        }
+        if let Achievement::Kills { enemy, .. } = &self.requirement {
+            if !rules.enemies.enemies.contains_key(enemy) {
+                return Err(format!("unknown enemy {:?}", enemy.0));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ClassLibrary {
+    pub fn get(&self, name: &str) -> Option<&ClassDefinition> {
+        self.classes.iter().find(|class| class.name == name)
+    }
+
+    /// The file the class high score used to be saved to, before profiles.
+    pub fn highscore_file(&self, name: &str) -> String {
+        if name == self.default {
+            "highscore.json".to_owned()
+        } else {
+            format!("highscore_{}.json", name.to_lowercase())
+        }
+    }
+
+    pub fn validate(&self, attacks: &AttackLibrary) -> Result<(), String> {
+        for (i, class) in self.classes.iter().enumerate() {
+            if self.classes[..i]
+                .iter()
+                .any(|other| other.name == class.name)
+            {
+                return Err(format!("class {:?} is defined more than once", class.name));
+            }
+            class
+                .validate(attacks)
+                .map_err(|error| format!("class {:?}: {}", class.name, error))?;
+        }
+        if self.get(&self.default).is_none() {
+            return Err(format!("unknown default class {:?}", self.default));
+        }
+        Ok(())
+    }
+}
+
+impl ClassDefinition {
+    fn validate(&self, attacks: &AttackLibrary) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("name must not be empty".to_owned());
+        }
+        if self.attacks.is_empty() {
+            return Err("there must be at least one starting attack".to_owned());
+        }
+        for id in self.attacks.iter().chain(&self.potential) {
+            if attacks.get(id).is_none() {
+                return Err(format!("unknown attack {:?}", id.0));
+            }
+        }
+        if self.move_time <= 0.0 {
+            return Err("move time must be positive".to_owned());
+        }
+        if !(self.attacks.len()..=SLOTS_REQUIREMENTS.len()).contains(&self.attack_slots) {
+            return Err(format!(
+                "attack slots must be between the number of starting attacks and {}",
+                SLOTS_REQUIREMENTS.len()
+            ));
+        }
+        let ultimate_upgrades = Ultimate::new(self.ultimate).upgrades();
+        for (i, upgrade) in self.upgrades.iter().enumerate() {
+            if self.upgrades[..i].contains(upgrade) {
+                return Err(format!("upgrade {:?} is listed more than once", upgrade));
+            }
+            match upgrade {
+                UpgradeType::BonusScore => {
+                    return Err(
+                        "bonus score is only offered when everything is maxed out".to_owned()
+                    );
+                }
+                UpgradeType::IncUltRadius
+                | UpgradeType::IncUltDuration
+                | UpgradeType::IncUltDamage
+                | UpgradeType::ReduceUltCooldown
+                    if !ultimate_upgrades.contains(upgrade) =>
+                {
+                    return Err(format!(
+                        "upgrade {:?} does not apply to the {:?} ultimate",
+                        upgrade, self.ultimate
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AttackLibrary {
+    pub fn get(&self, id: &AttackId) -> Option<&AttackDefinition> {
+        self.attacks.iter().find(|attack| attack.id == *id)
+    }
+
+    pub fn create(&self, id: &AttackId) -> Attack {
+        self.get(id)
+            .unwrap_or_else(|| panic!("Unknown attack {:?}", id.0))
+            .to_attack()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, attack) in self.attacks.iter().enumerate() {
+            if self.attacks[..i].iter().any(|other| other.id == attack.id) {
+                return Err(format!(
+                    "attack id {:?} is used more than once",
+                    attack.id.0
+                ));
+            }
+            attack
+                .validate()
+                .map_err(|error| format!("attack {:?}: {}", attack.id.0, error))?;
+        }
+        Ok(())
+    }
+}
+
+impl AttackDefinition {
+    fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("name must not be empty".to_owned());
+        }
+        if self.levels.is_empty() {
+            return Err("there must be at least one level".to_owned());
+        }
+        for (i, level) in self.levels.iter().enumerate() {
+            if level.cooldown < 0 {
+                return Err(format!("level {}: cooldown must not be negative", i + 1));
+            }
+            if level.damage == 0 {
+                return Err(format!("level {}: damage must be positive", i + 1));
+            }
+            let pattern = level
+                .pattern
+                .positions()
+                .map_err(|error| format!("level {}: {}", i + 1, error))?;
+            if pattern.is_empty() {
+                return Err(format!("level {}: pattern must not be empty", i + 1));
+            }
+            if pattern.contains(&Vec2::ZERO) {
+                return Err(format!(
+                    "level {}: pattern must not hit the caster itself",
+                    i + 1
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_attack(&self) -> Attack {
+        self.levels
+            .iter()
+            .rev()
+            .fold(None, |upgrade, level| {
+                let pattern = level.pattern.positions().expect("Invalid attack pattern");
+                Some(Attack::new(
+                    self.id.clone(),
+                    self.name.clone(),
+                    level.cooldown,
+                    level.damage,
+                    pattern,
+                    upgrade,
+                ))
+            })
+            .expect("Attack has no levels")
+    }
+}
+
+impl PatternDefinition {
+    pub fn positions(&self) -> Result<Vec<Position>, String> {
+        match self {
+            Self::Tiles(tiles) => Ok(tiles.iter().map(|&[x, y]| vec2(x, y)).collect()),
+            Self::Ascii(rows) => {
+                let mut caster = None;
+                let mut tiles = Vec::new();
+                for (row, line) in rows.iter().enumerate() {
+                    // The first row is the top one
+                    let y = -(row as Coord);
+                    for (x, tile) in line.chars().enumerate() {
+                        let pos = vec2(x as Coord, y);
+                        match tile {
+                            'P' => {
+                                if caster.replace(pos).is_some() {
+                                    return Err("pattern has more than one caster 'P'".to_owned());
+                                }
+                            }
+                            'X' => tiles.push(pos),
+                            '.' | ' ' => {}
+                            _ => return Err(format!("unknown pattern tile {:?}", tile)),
+                        }
+                    }
+                }
+                let caster = caster.ok_or_else(|| "pattern has no caster 'P'".to_owned())?;
+                Ok(tiles.into_iter().map(|pos| pos - caster).collect())
+            }
+        }
+    }
+}
+
+impl WaveConfig {
+    pub fn validate(&self, enemies: &EnemyConfig) -> Result<(), String> {
+        let pacing = &self.pacing;
+        if [
+            pacing.initial_budget,
+            pacing.budget_per_turn,
+            pacing.score_scaling,
+            pacing.enemy_pressure,
+            pacing.time_pressure,
+        ]
+        .iter()
+        .any(|&value| value < 0.0)
+        {
+            return Err("pacing values must not be negative".to_owned());
+        }
+        let affordable = |definition: &EnemyDefinition| {
+            definition
+                .cost
+                .is_some_and(|cost| cost <= pacing.max_budget)
+        };
+        if !enemies
+            .enemies
+            .values()
+            .any(|definition| definition.min_score == 0 && affordable(definition))
+        {
+            return Err("max_budget must afford an enemy with a min_score of 0".to_owned());
+        }
+        if let Some((typ, _)) = enemies
+            .enemies
+            .iter()
+            .find(|(_, definition)| definition.cost.is_some() && !affordable(definition))
+        {
+            return Err(format!(
+                "{:?} costs more than max_budget, omit its cost to only spawn it in waves",
+                typ.0
+            ));
+        }
+        let elites = &self.elites;
+        if elites.chance_per_score < 0.0 || !(0.0..=1.0).contains(&elites.max_chance) {
+            return Err("elite chances must be in range 0..=1".to_owned());
+        }
+        if elites.max_modifiers > 0 && elites.modifiers.is_empty() {
+            return Err("elites need at least one modifier".to_owned());
+        }
+        if self
+            .waves
+            .windows(2)
+            .any(|pair| pair[0].turn > pair[1].turn)
+        {
+            return Err("waves must be ordered by turn".to_owned());
+        }
+        for wave in &self.waves {
+            if wave.groups.is_empty() {
+                return Err(format!("wave on turn {} has no enemies", wave.turn));
+            }
+            for group in &wave.groups {
+                if group.count == 0 {
+                    return Err(format!(
+                        "wave on turn {}: count must be positive",
+                        wave.turn
+                    ));
+                }
+                if !enemies.enemies.contains_key(&group.enemy) {
+                    return Err(format!(
+                        "wave on turn {}: unknown enemy {:?}",
+                        wave.turn, group.enemy.0
+                    ));
+                }
+                if let Some(from) = group.from {
+                    from.validate()
+                        .map_err(|error| format!("wave on turn {}: {}", wave.turn, error))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MapLibrary {
+    pub fn get(&self, name: &str) -> Option<&MapDefinition> {
+        self.maps.iter().find(|map| map.name == name)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, map) in self.maps.iter().enumerate() {
+            if self.maps[..i].iter().any(|other| other.name == map.name) {
+                return Err(format!("map name {:?} is used more than once", map.name));
+            }
+            map.to_arena()
+                .map_err(|error| format!("map {:?}: {}", map.name, error))?;
+        }
+        if self.get(&self.default).is_none() {
+            return Err(format!("unknown default map {:?}", self.default));
+        }
+        Ok(())
+    }
+}
+
+impl MapDefinition {
+    /// Builds the arena with the player start at the origin.
+    pub fn to_arena(&self) -> Result<Arena, String> {
+        let width = self.layout.first().map_or(0, |row| row.chars().count());
+        if width == 0 {
+            return Err("layout must not be empty".to_owned());
+        }
+        if self.layout.iter().any(|row| row.chars().count() != width) {
+            return Err("all layout rows must have the same length".to_owned());
+        }
+        let height = self.layout.len();
+
+        let mut start = None;
+        let mut tiles = Vec::new();
+        let mut spawns = Vec::new();
+        for (row, line) in self.layout.iter().enumerate() {
+            let y = (height - 1 - row) as Coord;
+            for (x, tile) in line.chars().enumerate() {
+                let pos = vec2(x as Coord, y);
+                let tile = match tile {
+                    '.' => Tile::Floor,
+                    '#' => Tile::Wall,
+                    'O' => Tile::Pit,
+                    '~' => Tile::Slow,
+                    '|' => Tile::Barrier,
+                    'P' => {
+                        if start.replace(pos).is_some() {
+                            return Err("layout has more than one player start 'P'".to_owned());
+                        }
+                        Tile::Floor
+                    }
+                    '1'..='9' => {
+                        spawns.push((pos, tile.to_digit(10).unwrap()));
+                        Tile::Floor
+                    }
+                    _ => return Err(format!("unknown map tile {:?}", tile)),
+                };
+                if tile != Tile::Floor {
+                    tiles.push((pos, tile));
+                }
+            }
+        }
+        let start = start.ok_or_else(|| "layout has no player start 'P'".to_owned())?;
+        if spawns.is_empty() {
+            return Err("layout has no spawn points".to_owned());
+        }
+
+        let mut arena = Arena::new(AABB::from_corners(
+            -start,
+            vec2(width as Coord - 1, height as Coord - 1) - start,
+        ));
+        arena.wrapping = vec2(self.wrap_x, self.wrap_y);
+        arena.tiles = tiles
+            .into_iter()
+            .map(|(pos, tile)| (pos - start, tile))
+            .collect();
+        arena.spawns = spawns
+            .into_iter()
+            .map(|(pos, weight)| (pos - start, weight))
+            .collect();
+        Ok(arena)
+    }
+}
+
+impl ExperienceCurve {
+    pub fn level_score(&self, level: u32) -> Score {
+        if let Some(&score) = self.table.get(level as usize) {
+            return score;
+        }
+        let score = match self.extension {
+            CurveExtension::Arithmetic { step } => {
+                let last = *self
+                    .table
+                    .last()
+                    .expect("Arithmetic extension requires a table")
+                    as u64;
+                let gap = match self.table.len() {
+                    1 => last,
+                    len => last - self.table[len - 2] as u64,
+                };
+                let extra = (level as usize + 1 - self.table.len()) as u64;
+                last + extra * gap + step as u64 * extra * (extra + 1) / 2
+            }
+            CurveExtension::Polynomial {
+                base,
+                linear,
+                quadratic,
+            } => {
+                let level = level as f32;
+                (base + linear * level + quadratic * level * level).ceil() as u64
+            }
+        };
+        score.min(Score::MAX as u64) as Score
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.table.first() == Some(&0) {
+            return Err("the first level must require some experience".to_owned());
+        }
+        if self.table.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err("table must be strictly increasing".to_owned());
+        }
+        match self.extension {
+            CurveExtension::Arithmetic { .. } => {
+                if self.table.is_empty() {
+                    return Err("arithmetic extension requires a table".to_owned());
+                }
+            }
+            CurveExtension::Polynomial {
+                base,
+                linear,
+                quadratic,
+            } => {
+                if linear < 0.0 || quadratic < 0.0 || linear + quadratic < 1.0 {
+                    return Err("polynomial extension must grow by at least 1 per level".to_owned());
+                }
+                let first = self.level_score(self.table.len() as u32);
+                if first <= self.table.last().copied().unwrap_or(0) {
+                    return Err("polynomial extension must continue above the table".to_owned());
+                }
+            }
+        }
+        Ok(())
+    }
+}
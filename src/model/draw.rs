@@ -3,26 +3,49 @@ use super::*;
 impl GameState {
     pub fn draw(&mut self, framebuffer: &mut ugli::Framebuffer) {
         let framebuffer_size = framebuffer.size().map(|x| x as f32);
+        self.framebuffer_size = framebuffer_size;
+        let simulation = &self.simulation;
+        let saves = self.shared.saves.borrow();
+        let palette = saves.settings.palette.palette();
 
         // Game camera
         let mut renderer = Renderer::new(&self.geng, &self.assets, &self.camera, framebuffer);
 
         // Grid
         renderer.draw_grid(
-            self.arena_bounds,
+            simulation.arena.bounds,
             TILE_SIZE,
             -TILE_SIZE / 2.0,
             GRID_WIDTH,
-            GRID_COLOR,
+            palette.grid,
         );
 
+        // Tiles
+        for (&pos, tile) in &simulation.arena.tiles {
+            let color = match tile {
+                Tile::Floor => continue,
+                Tile::Wall => palette.wall,
+                Tile::Pit => palette.pit,
+                Tile::Slow => palette.slow,
+                Tile::Barrier => palette.barrier,
+            };
+            renderer.draw_aabb(logic::grid_cell_aabb(pos, TILE_SIZE), color);
+        }
+
         // Wrap indicator
-        if self.player.position.x == self.arena_bounds.x_min
-            || self.player.position.x == self.arena_bounds.x_max
+        let can_wrap = simulation.arena.tile(simulation.player.position) != Tile::Barrier;
+        if can_wrap
+            && simulation.arena.wrapping.x
+            && (simulation.player.position.x == simulation.arena.bounds.x_min
+                || simulation.player.position.x == simulation.arena.bounds.x_max)
         {
-            let left_pos = vec2(self.arena_bounds.x_min, self.player.position.y).map(|x| x as f32);
-            let right_pos =
-                vec2(self.arena_bounds.x_max + 1, self.player.position.y).map(|x| x as f32);
+            let left_pos =
+                vec2(simulation.arena.bounds.x_min, simulation.player.position.y).map(|x| x as f32);
+            let right_pos = vec2(
+                simulation.arena.bounds.x_max + 1,
+                simulation.player.position.y,
+            )
+            .map(|x| x as f32);
             for pos in [left_pos, right_pos]
                 .into_iter()
                 .map(|x| x - TILE_SIZE / 2.0)
@@ -31,16 +54,22 @@ impl GameState {
                     AABB::point(pos)
                         .extend_symmetric(vec2(GRID_WIDTH / 2.0, 0.0))
                         .extend_up(TILE_SIZE.y),
-                    WRAP_COLOR,
+                    palette.wrap,
                 );
             }
         }
-        if self.player.position.y == self.arena_bounds.y_min
-            || self.player.position.y == self.arena_bounds.y_max
+        if can_wrap
+            && simulation.arena.wrapping.y
+            && (simulation.player.position.y == simulation.arena.bounds.y_min
+                || simulation.player.position.y == simulation.arena.bounds.y_max)
         {
-            let bottom_pos =
-                vec2(self.player.position.x, self.arena_bounds.y_max + 1).map(|x| x as f32);
-            let top_pos = vec2(self.player.position.x, self.arena_bounds.y_min).map(|x| x as f32);
+            let bottom_pos = vec2(
+                simulation.player.position.x,
+                simulation.arena.bounds.y_max + 1,
+            )
+            .map(|x| x as f32);
+            let top_pos =
+                vec2(simulation.player.position.x, simulation.arena.bounds.y_min).map(|x| x as f32);
             for pos in [bottom_pos, top_pos]
                 .into_iter()
                 .map(|x| x - TILE_SIZE / 2.0)
@@ -49,56 +78,167 @@ impl GameState {
                     AABB::point(pos)
                         .extend_symmetric(vec2(0.0, GRID_WIDTH / 2.0))
                         .extend_right(TILE_SIZE.y),
-                    WRAP_COLOR,
+                    palette.wrap,
                 );
             }
         }
 
         // Spawns
-        for (spawn_pos, _) in &self.spawns {
-            let aabb = logic::grid_cell_aabb(*spawn_pos, TILE_SIZE);
-            let aabb = AABB::point(aabb.center()).extend_symmetric(WARNING_SIZE / 2.0);
+        for spawn in &simulation.spawns {
+            let offset = (spawn.enemy.size - 1) as f32 / 2.0 * TILE_SIZE;
+            let aabb = logic::grid_cell_aabb(spawn.position, TILE_SIZE);
+            let aabb = AABB::point(aabb.center() + offset).extend_symmetric(WARNING_SIZE / 2.0);
             renderer.draw_texture(&self.assets.exclamation, aabb);
+            if spawn.turns_left > 1 {
+                renderer.draw_text(
+                    &spawn.turns_left.to_string(),
+                    aabb.top_right(),
+                    vec2(0.0, 1.0),
+                    SPAWN_TURNS_SIZE,
+                    Color::GRAY,
+                );
+            }
         }
 
         // Enemies
-        for enemy in &self.enemies {
-            renderer.draw_circle(
-                enemy.interpolation.current() * TILE_SIZE,
-                UNIT_RADIUS,
-                enemy.color,
-            );
+        for enemy in &simulation.enemies {
+            let size = enemy.size as f32;
+            let center =
+                (enemy.interpolation.current() + vec2(size - 1.0, size - 1.0) / 2.0) * TILE_SIZE;
+            let radius = UNIT_RADIUS * size;
+            renderer.draw_circle(center, radius, enemy.current_color());
+            let cue = AABB::point(center).extend_uniform(radius * 0.5);
+            match enemy.movement {
+                MovementType::Knight => {
+                    renderer.draw_aabb_frame(
+                        AABB::point(center).extend_uniform(radius),
+                        SHAPE_CUE_WIDTH,
+                        SHAPE_CUE_COLOR,
+                    );
+                }
+                MovementType::Bishop => {
+                    renderer.draw_cross(cue, SHAPE_CUE_WIDTH, SHAPE_CUE_COLOR);
+                }
+                MovementType::Teleporter { charging, .. } => {
+                    renderer.draw_aabb(cue.extend_uniform(-radius * 0.2), SHAPE_CUE_COLOR);
+                    if charging {
+                        renderer.draw_circle_with_cut(
+                            center,
+                            radius * 1.3,
+                            radius * 1.5,
+                            palette.telegraph,
+                        );
+                    }
+                }
+                MovementType::Orbiter { .. } => {
+                    renderer.draw_circle_with_cut(
+                        center,
+                        radius * 1.5,
+                        radius * 1.5 + SHAPE_CUE_WIDTH,
+                        enemy.color,
+                    );
+                }
+                MovementType::Flee { .. } => {
+                    renderer.draw_circle(center, radius * 0.3, SHAPE_CUE_COLOR);
+                }
+                _ => {}
+            }
+            if enemy.armored && !enemy.armor_used {
+                renderer.draw_circle_with_cut(center, radius, radius + ARMOR_WIDTH, Color::GRAY);
+            }
+            // Modifier outlines
+            let mut outline = radius + ARMOR_WIDTH;
+            for modifier in &enemy.modifiers {
+                if *modifier == Modifier::Shielded && !enemy.shield {
+                    continue;
+                }
+                renderer.draw_circle_with_cut(
+                    center,
+                    outline,
+                    outline + MODIFIER_OUTLINE_WIDTH,
+                    modifier.color(),
+                );
+                outline += MODIFIER_OUTLINE_WIDTH;
+            }
+            if enemy.max_health > 1 {
+                // Health pips
+                let spacing = HEALTH_PIP_RADIUS * 3.0;
+                let start = center
+                    + vec2(
+                        -spacing * (enemy.max_health - 1) as f32 / 2.0,
+                        radius + HEALTH_PIP_RADIUS * 2.0,
+                    );
+                for i in 0..enemy.max_health {
+                    let color = if i < enemy.health {
+                        enemy.color
+                    } else {
+                        Color::GRAY
+                    };
+                    renderer.draw_circle(
+                        start + vec2(spacing * i as f32, 0.0),
+                        HEALTH_PIP_RADIUS,
+                        color,
+                    );
+                }
+            }
         }
 
         // Ultimate
-        if let Some(origin) = self.using_ultimate {
-            for pos in self
+        if let Some(origin) = simulation.using_ultimate {
+            for pos in simulation
                 .player_ultimate
                 .deltas()
-                .map(|pos| logic::wrap_pos(pos + origin, self.arena_bounds).0)
+                .filter_map(|pos| simulation.arena.wrap(pos + origin))
+                .map(|(pos, _)| pos)
+                .filter(|&pos| simulation.arena.is_walkable(pos))
                 .map(|pos| logic::grid_cell_aabb(pos, TILE_SIZE).center())
             {
                 renderer.draw_circle(pos, 0.1, Color::MAGENTA);
             }
         }
 
+        // Decoy
+        if let Some(decoy) = &simulation.decoy {
+            let pos = logic::grid_cell_aabb(decoy.position, TILE_SIZE).center();
+            let mut color = simulation.player.color;
+            color.a = PLAYER_ULTIMATE_ALPHA;
+            renderer.draw_circle(pos, UNIT_RADIUS, color);
+            renderer.draw_text(
+                &decoy.turns_left.to_string(),
+                pos,
+                vec2(0.5, 0.5),
+                SPAWN_TURNS_SIZE,
+                Color::WHITE,
+            );
+        }
+
         // Player
-        let mut color = self.player.color;
-        color.a = if self.using_ultimate.is_some() {
+        let mut color = simulation.player.color;
+        color.a = if simulation.using_ultimate.is_some() {
             PLAYER_ULTIMATE_ALPHA
         } else {
             1.0
         };
         renderer.draw_circle(
-            self.player.interpolation.current() * TILE_SIZE,
+            simulation.player.interpolation.current() * TILE_SIZE,
             UNIT_RADIUS,
             color,
         );
 
         // Damage
-        for &pos in &self.damages {
+        for &(caster, pos) in &simulation.damages {
             let aabb = logic::grid_cell_aabb(pos, TILE_SIZE).extend_uniform(-DAMAGE_EXTRA_SPACE);
-            renderer.draw_cross(aabb, DAMAGE_WIDTH, DAMAGE_COLOR);
+            let color = match caster {
+                Caster::Player => palette.damage,
+                Caster::Enemy { .. } | Caster::Explosion => palette.enemy_damage,
+            };
+            renderer.draw_cross(aabb, DAMAGE_WIDTH, color);
+        }
+
+        // Enemy attack warnings
+        for &pos in simulation.enemies.iter().flat_map(|enemy| &enemy.telegraph) {
+            let aabb = logic::grid_cell_aabb(pos, TILE_SIZE).extend_uniform(-DAMAGE_EXTRA_SPACE);
+            renderer.draw_cross(aabb, DAMAGE_WIDTH, palette.telegraph);
         }
 
         // UI camera
@@ -118,9 +258,9 @@ impl GameState {
             framebuffer_size.map(|x| x - ATTACKS_OFFSET),
         );
         renderer.draw_attacks(
-            &self.player_attacks,
-            4,
-            logic::attack_slots(*self.highscore),
+            &simulation.player_attacks,
+            simulation.max_attack_slots,
+            simulation.attack_slots(),
             attacks_aabb,
             ATTACKS_BORDER_WIDTH,
             ATTACKS_BORDER_COLOR,
@@ -132,7 +272,7 @@ impl GameState {
             vec2(framebuffer_size.x - ATTACKS_OFFSET, ATTACKS_OFFSET),
         );
         renderer.draw_ultimate(
-            &self.player_ultimate,
+            &simulation.player_ultimate,
             ultimate_aabb,
             ATTACKS_BORDER_WIDTH,
             ATTACKS_BORDER_COLOR,
@@ -141,62 +281,121 @@ impl GameState {
 
         // Score text
         renderer.draw_text(
-            &format!("Points: {}", self.score),
+            &format!("Points: {}", simulation.score),
             vec2(10.0, framebuffer_size.y - 10.0),
             vec2(0.0, 1.0),
             30.0,
             Color::GRAY,
         );
         renderer.draw_text(
-            &format!("High Score: {}", *self.highscore),
+            &format!("High Score: {}", simulation.highscore),
             vec2(10.0, framebuffer_size.y - 100.0),
             vec2(0.0, 1.0),
             20.0,
             Color::GRAY,
         );
 
+        // Upcoming waves
+        for (i, wave) in simulation.director.waves.iter().take(3).enumerate() {
+            let enemies = wave
+                .groups
+                .iter()
+                .map(|group| match group.from {
+                    None => format!("{} {}", group.count, group.enemy.0),
+                    Some(from) => format!("{} {} ({:?})", group.count, group.enemy.0, from),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            let (text, color) = if wave.boss {
+                (
+                    format!(
+                        "BOSS in {}: {}",
+                        wave.turn - simulation.director.turn,
+                        enemies
+                    ),
+                    BOSS_WAVE_COLOR,
+                )
+            } else {
+                (
+                    format!("In {}: {}", wave.turn - simulation.director.turn, enemies),
+                    Color::GRAY,
+                )
+            };
+            renderer.draw_text(
+                &text,
+                vec2(10.0, framebuffer_size.y - 150.0 - i as f32 * 30.0),
+                vec2(0.0, 1.0),
+                20.0,
+                color,
+            );
+        }
+
+        // Replay
+        if let Some(playback) = &self.playback {
+            renderer.draw_text(
+                &format!("Replay x{} (Up/Down to change speed)", playback.speed),
+                vec2(10.0, 10.0),
+                vec2(0.0, 0.0),
+                20.0,
+                Color::GRAY,
+            );
+        }
+
         // Move time
-        renderer.draw_text(
-            &format!("Time left: {:.1}", self.move_time_left),
-            vec2(framebuffer_size.x / 2.0, framebuffer_size.y - 10.0),
-            vec2(0.5, 1.0),
-            20.0,
-            Color::GRAY,
-        );
-        let time_aabb = AABB::point(vec2(framebuffer_size.x / 2.0, framebuffer_size.y - 100.0))
-            .extend_symmetric(TIME_BAR_SIZE / 2.0);
-        renderer.draw_aabb(time_aabb, TIME_BAR_BACKGROUND_COLOR);
-        let time_ratio = self.move_time_left / self.move_time_limit;
-        let time_bar = time_aabb.extend_symmetric(vec2(0.0, -TIME_BAR_INNER_SPACE));
-        let time_bar = time_bar.extend_right((time_ratio - 1.0) * time_bar.width());
-        let a = TIME_BAR_LEFT_COLOR;
-        let b = TIME_BAR_RIGHT_COLOR;
-        let color_right = Color {
-            r: a.r + (b.r - a.r) * time_ratio,
-            g: a.g + (b.g - a.g) * time_ratio,
-            b: a.b + (b.b - a.b) * time_ratio,
-            a: a.a + (b.a - a.a) * time_ratio,
-        };
-        renderer.draw_aabb(time_bar, color_right);
-        renderer.draw_aabb_frame(time_aabb, TIME_BORDER_WIDTH, TIME_BORDER_COLOR);
+        if saves.settings.timer.show_text() {
+            renderer.draw_text(
+                &format!("Time left: {:.1}", simulation.move_time_left),
+                vec2(framebuffer_size.x / 2.0, framebuffer_size.y - 10.0),
+                vec2(0.5, 1.0),
+                20.0,
+                Color::GRAY,
+            );
+        }
+        if simulation.time_stop > 0 {
+            renderer.draw_text(
+                &format!("Time stopped: {}", simulation.time_stop),
+                vec2(framebuffer_size.x / 2.0, framebuffer_size.y - 40.0),
+                vec2(0.5, 1.0),
+                20.0,
+                Color::MAGENTA,
+            );
+        }
+        if saves.settings.timer.show_bar() {
+            let time_aabb = AABB::point(vec2(framebuffer_size.x / 2.0, framebuffer_size.y - 100.0))
+                .extend_symmetric(TIME_BAR_SIZE / 2.0);
+            renderer.draw_aabb(time_aabb, TIME_BAR_BACKGROUND_COLOR);
+            let time_ratio = simulation.move_time_left / simulation.move_time_limit;
+            let time_bar = time_aabb.extend_symmetric(vec2(0.0, -TIME_BAR_INNER_SPACE));
+            let time_bar = time_bar.extend_right((time_ratio - 1.0) * time_bar.width());
+            let a = TIME_BAR_LEFT_COLOR;
+            let b = TIME_BAR_RIGHT_COLOR;
+            let color_right = Color {
+                r: a.r + (b.r - a.r) * time_ratio,
+                g: a.g + (b.g - a.g) * time_ratio,
+                b: a.b + (b.b - a.b) * time_ratio,
+                a: a.a + (b.a - a.a) * time_ratio,
+            };
+            renderer.draw_aabb(time_bar, color_right);
+            renderer.draw_aabb_frame(time_aabb, TIME_BORDER_WIDTH, TIME_BORDER_COLOR);
+        }
 
         // Experience
         let exp_aabb = AABB::point(vec2(EXPERIENCE_BAR_SIZE.x * 2.0, framebuffer_size.y / 2.0))
             .extend_symmetric(EXPERIENCE_BAR_SIZE / 2.0);
         renderer.draw_aabb(exp_aabb, EXPERIENCE_BAR_BACKGROUND_COLOR);
-        let exp_ratio = self.experience.get_ratio();
+        let exp_ratio = simulation.experience.get_ratio();
         let exp_bar = exp_aabb.extend_symmetric(vec2(-EXPERIENCE_BAR_INNER_SPACE, 0.0));
         let exp_bar = exp_bar.extend_up((exp_ratio - 1.0) * exp_bar.height());
         renderer.draw_aabb(exp_bar, EXPERIENCE_BAR_COLOR);
         renderer.draw_aabb_frame(exp_aabb, EXPERIENCE_BORDER_WIDTH, EXPERIENCE_BORDER_COLOR);
         let level_aabb = AABB::point(vec2(exp_aabb.center().x, exp_aabb.y_min))
             .extend_uniform(EXPERIENCE_BAR_SIZE.x);
-        renderer.draw_level(self.experience.level, level_aabb);
+        renderer.draw_level(simulation.experience.level, level_aabb);
         let level_aabb = level_aabb.translate(vec2(0.0, exp_aabb.height()));
-        renderer.draw_level(self.experience.level + 1, level_aabb);
+        renderer.draw_level(simulation.experience.level + 1, level_aabb);
 
         // Upgrade menu
-        if let Some(upgrade_menu) = &self.upgrade_menu {
+        if let Some(upgrade_menu) = &simulation.upgrade_menu {
             let upgrades_width = (UPGRADE_SIZE.x + UPGRADE_EXTRA_SPACE)
                 * upgrade_menu.options.len() as f32
                 - UPGRADE_EXTRA_SPACE;
@@ -209,13 +408,10 @@ impl GameState {
                 ),
                 UPGRADE_BACKGROUND_COLOR,
             );
-            let upgrade_aabb = AABB::ZERO.extend_symmetric(UPGRADE_SIZE / 2.0);
-            let left_pos =
-                framebuffer_size / 2.0 - vec2((upgrades_width - UPGRADE_SIZE.x) / 2.0, 0.0);
-            for (i, (upgrade, attack_index)) in upgrade_menu.options.iter().enumerate() {
-                let aabb = upgrade_aabb.translate(
-                    left_pos + i as f32 * vec2(UPGRADE_SIZE.x + UPGRADE_EXTRA_SPACE, 0.0),
-                );
+            let cards = upgrade_menu.card_aabbs(framebuffer_size);
+            for (i, ((upgrade, attack_index), aabb)) in
+                upgrade_menu.options.iter().zip(cards).enumerate()
+            {
                 renderer.draw_aabb_frame(aabb, UPGRADE_FRAME_WIDTH, UPGRADE_FRAME_COLOR);
                 if i == upgrade_menu.choice {
                     renderer.draw_aabb(
@@ -231,8 +427,12 @@ impl GameState {
                             aabb.top_right(),
                             vec2(aabb.x_min, aabb.y_max - text_height),
                         );
-                        renderer.draw_text_fit("NEW ATTACK", text_aabb, UPGRADE_TEXT_COLOR);
-                        let new_attack = &self.potential_attacks[attack_index.unwrap()];
+                        let new_attack = &simulation.potential_attacks[attack_index.unwrap()];
+                        renderer.draw_text_fit(
+                            &format!("NEW: {}", new_attack.name),
+                            text_aabb,
+                            UPGRADE_TEXT_COLOR,
+                        );
                         let cd_height = aabb.height() / 8.0;
                         let cd_aabb = AABB::from_corners(
                             aabb.bottom_left(),
@@ -249,32 +449,50 @@ impl GameState {
                         renderer.draw_attack(new_attack, attack_aabb);
                         vec![]
                     }
-                    UpgradeType::IncUltRadius => vec![
-                        format!("Radius"),
-                        format!("Ultimate"),
-                        format!(
-                            "{} -> {}",
-                            self.player_ultimate.radius,
-                            self.player_ultimate.radius + 1
-                        ),
-                    ],
+                    UpgradeType::IncUltRadius => {
+                        let radius = simulation.player_ultimate.radius().unwrap_or_default();
+                        vec![
+                            format!("Radius"),
+                            simulation.player_ultimate.name().to_owned(),
+                            format!("{} -> {}", radius, radius + 1),
+                        ]
+                    }
+                    UpgradeType::IncUltDuration => {
+                        let turns = simulation.player_ultimate.turns().unwrap_or_default();
+                        vec![
+                            format!("DURATION"),
+                            simulation.player_ultimate.name().to_owned(),
+                            format!("{} -> {}", turns, turns + 1),
+                        ]
+                    }
+                    UpgradeType::IncUltDamage => {
+                        let damage = match simulation.player_ultimate {
+                            Ultimate::Shockwave { damage, .. } => damage,
+                            _ => 0,
+                        };
+                        vec![
+                            format!("DAMAGE"),
+                            simulation.player_ultimate.name().to_owned(),
+                            format!("{} -> {}", damage, damage + 1),
+                        ]
+                    }
                     UpgradeType::ReduceUltCooldown => {
+                        let cooldown = simulation.player_ultimate.action().cooldown;
                         vec![
                             format!("COOLDOWN"),
-                            format!("Ultimate"),
-                            format!(
-                                "{} -> {}",
-                                self.player_ultimate.action.cooldown,
-                                self.player_ultimate.action.cooldown + 1
-                            ),
+                            simulation.player_ultimate.name().to_owned(),
+                            format!("{} -> {}", cooldown, cooldown - 1),
                         ]
                     }
                     UpgradeType::IncDeathTimer => vec![format!("TIMER"), format!("+2 Sec")],
+                    UpgradeType::BonusScore => {
+                        vec![format!("BONUS"), format!("+{} Points", BONUS_SCORE)]
+                    }
                     UpgradeType::ReduceAttackCooldown => {
-                        let attack = &self.player_attacks[attack_index.unwrap()];
+                        let attack = &simulation.player_attacks[attack_index.unwrap()];
                         vec![
                             format!("COOLDOWN"),
-                            format!("Attack {}", attack_index.unwrap() + 1),
+                            attack.name.clone(),
                             format!(
                                 "{} -> {}",
                                 attack.action.cooldown,
@@ -282,12 +500,18 @@ impl GameState {
                             ),
                         ]
                     }
-                    UpgradeType::UpgradeAttack => {
+                    UpgradeType::IncAttackDamage => {
+                        let attack = &simulation.player_attacks[attack_index.unwrap()];
                         vec![
-                            format!("Upgrade"),
-                            format!("Attack {}", attack_index.unwrap() + 1),
+                            format!("DAMAGE"),
+                            attack.name.clone(),
+                            format!("{} -> {}", attack.damage, attack.damage + 1),
                         ]
                     }
+                    UpgradeType::UpgradeAttack => {
+                        let attack = &simulation.player_attacks[attack_index.unwrap()];
+                        vec![format!("Upgrade"), attack.name.clone()]
+                    }
                 };
                 if texts.len() > 0 {
                     let mut aabb =
@@ -300,6 +524,16 @@ impl GameState {
             }
         }
 
+        // Settings
+        if let Some(menu) = &self.settings_menu {
+            menu.draw(
+                &mut renderer,
+                framebuffer_size,
+                &saves.settings,
+                &self.shared.audio.settings(),
+            );
+        }
+
         // Fade
         let mut color = FADE_COLOR;
         color.a = self.fade.current;
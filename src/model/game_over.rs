@@ -0,0 +1,146 @@
+use super::*;
+
+pub struct RunSummary {
+    pub seed: u64,
+    pub score: Score,
+    pub highscore: Score,
+    pub level: u32,
+    pub kills: BTreeMap<EnemyType, u32>,
+    /// Currency earned, if the run was recorded in the profile.
+    pub earned: Option<u32>,
+    pub currency: u32,
+}
+
+pub struct GameOver {
+    geng: Geng,
+    assets: Rc<Assets>,
+    shared: Shared,
+    controls: InputMap,
+    summary: RunSummary,
+    result: Rc<Cell<Option<MenuAction>>>,
+    camera: Camera2d,
+    transition: Option<geng::Transition>,
+}
+
+impl GameOver {
+    pub fn new(
+        geng: &Geng,
+        assets: &Rc<Assets>,
+        shared: Shared,
+        controls: InputMap,
+        summary: RunSummary,
+        result: Rc<Cell<Option<MenuAction>>>,
+    ) -> Self {
+        Self {
+            geng: geng.clone(),
+            assets: assets.clone(),
+            shared,
+            controls,
+            summary,
+            result,
+            camera: Camera2d {
+                center: Vec2::ZERO,
+                rotation: 0.0,
+                fov: 1080.0,
+            },
+            transition: None,
+        }
+    }
+
+    fn handle_input(&mut self, input: Input) {
+        if self.transition.is_some() {
+            return;
+        }
+        let action = if self.controls.is_confirm(input)
+            || self.controls.command(input) == Some(Command::Restart)
+        {
+            MenuAction::Restart
+        } else if self.controls.is_menu(input) {
+            MenuAction::MainMenu
+        } else {
+            return;
+        };
+        self.result.set(Some(action));
+        self.shared.audio.play(SoundType::Select);
+        self.transition = Some(geng::Transition::Pop);
+    }
+}
+
+impl geng::State for GameOver {
+    fn update(&mut self, delta_time: f64) {
+        self.shared.audio.update(delta_time as f32);
+        let inputs = self.shared.gamepad.borrow_mut().poll();
+        for input in inputs {
+            self.handle_input(input);
+        }
+    }
+
+    fn draw(&mut self, framebuffer: &mut ugli::Framebuffer) {
+        ugli::clear(framebuffer, Some(Color::BLACK), None);
+        let framebuffer_size = framebuffer.size().map(|x| x as f32);
+        let framebuffer_size = vec2(
+            self.camera.fov / framebuffer_size.y * framebuffer_size.x,
+            self.camera.fov,
+        );
+        self.camera.center = framebuffer_size / 2.0;
+        let mut renderer = Renderer::new(&self.geng, &self.assets, &self.camera, framebuffer);
+
+        let summary = &self.summary;
+        let mut pos = vec2(framebuffer_size.x / 2.0, framebuffer_size.y - 150.0);
+        renderer.draw_text("GAME OVER", pos, vec2(0.5, 1.0), 60.0, GAME_OVER_TEXT_COLOR);
+        pos.y -= 120.0;
+
+        let mut lines = vec![
+            if summary.score > summary.highscore {
+                format!("Score: {} (new best!)", summary.score)
+            } else {
+                format!("Score: {} (best {})", summary.score, summary.highscore)
+            },
+            format!("Level: {}", summary.level),
+        ];
+        if let Some(earned) = summary.earned {
+            lines.push(format!("+{} coins ({} total)", earned, summary.currency));
+        }
+        lines.push(String::new());
+        if summary.kills.is_empty() {
+            lines.push("No kills".to_owned());
+        } else {
+            lines.push("Kills:".to_owned());
+            lines.extend(
+                summary
+                    .kills
+                    .iter()
+                    .map(|(typ, count)| format!("{}: {}", typ.0, count)),
+            );
+        }
+        for line in &lines {
+            renderer.draw_text(line, pos, vec2(0.5, 1.0), 30.0, GAME_OVER_TEXT_COLOR);
+            pos.y -= 40.0;
+        }
+
+        renderer.draw_text(
+            &format!("Seed: {}", summary.seed),
+            vec2(framebuffer_size.x / 2.0, 160.0),
+            vec2(0.5, 0.0),
+            20.0,
+            Color::GRAY,
+        );
+        renderer.draw_text(
+            "(Enter) Restart   (Esc) Main menu",
+            vec2(framebuffer_size.x / 2.0, 100.0),
+            vec2(0.5, 0.0),
+            30.0,
+            Color::WHITE,
+        );
+    }
+
+    fn handle_event(&mut self, event: geng::Event) {
+        if let geng::Event::KeyDown { key } = event {
+            self.handle_input(Input::Key(key));
+        }
+    }
+
+    fn transition(&mut self) -> Option<geng::Transition> {
+        self.transition.take()
+    }
+}
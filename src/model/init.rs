@@ -2,273 +2,228 @@ use super::*;
 
 impl GameState {
     pub fn reset(&mut self) {
-        let state = Self::new(&self.geng, &self.assets);
-        *self = state;
+        let seed = global_rng().gen();
+        let map = self.simulation.map.clone();
+        let class = self.simulation.class.clone();
+        let highscore = self.shared.saves.borrow().profile.highscore(&class);
+        let locked = self
+            .shared
+            .saves
+            .borrow()
+            .profile
+            .locked(&self.assets.rules);
+        self.simulation =
+            Simulation::new(highscore, seed, &map, &class, &locked, &self.assets.rules);
+        self.recording = Replay::new(seed, highscore, &map, &class, locked);
+        self.playback = None;
+        self.earned = None;
+        self.time = 0.0;
+        self.fade = Fade::fade_in();
     }
 
-    pub fn new(geng: &Geng, assets: &Rc<Assets>) -> Self {
-        // assets.music.play().set_volume(0.05);
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_replay(&self) {
+        if self.playback.is_some() || self.recording.commands.is_empty() {
+            return;
+        }
+        let path = format!(
+            "replays/{}_{}.json",
+            self.recording.seed, self.simulation.score
+        );
+        if let Err(error) = self.recording.save(&path) {
+            log::error!("Failed to save replay to {}: {}", path, error);
+        }
+    }
+
+    pub fn new(
+        geng: &Geng,
+        assets: &Rc<Assets>,
+        seed: u64,
+        map: &str,
+        class: &str,
+        shared: Shared,
+    ) -> Self {
+        shared.audio.play_music(MusicTrack::Game);
+        let highscore = shared.saves.borrow().profile.highscore(class);
+        let locked = shared.saves.borrow().profile.locked(&assets.rules);
+        let simulation = Simulation::new(highscore, seed, map, class, &locked, &assets.rules);
+        let bounds = simulation.arena.bounds;
+        let camera = Camera2d {
+            center: (bounds.bottom_left() + bounds.top_right()).map(|x| x as f32) * TILE_SIZE / 2.0,
+            rotation: 0.0,
+            fov: (bounds.height() + 1) as f32 * TILE_SIZE.y + 5.0,
+        };
         Self {
             geng: geng.clone(),
             assets: assets.clone(),
-            arena_bounds: AABB::from_corners(vec2(-4, -4), vec2(5, 5)),
-            highscore: AutoSave::load(static_path().join("highscore.json").to_str().unwrap()),
-            score: 0,
-            move_time_limit: 6.0,
-            move_time_left: 6.0,
-            experience: Experience::new(),
-            using_ultimate: None,
-            upgrade_menu: None,
-            freeze_move_timer: true,
-            spawns: vec![],
-            sounds: vec![],
-            volume: 0.2,
-            fade: Fade {
-                min: 0.0,
-                max: 1.0,
-                current: 1.0,
-                speed: -1.0 / FADE_TIME,
-            },
-            camera: Camera2d {
-                center: TILE_SIZE / 2.0,
-                rotation: 0.0,
-                fov: 15.0,
-            },
+            simulation,
+            time: 0.0,
+            recording: Replay::new(seed, highscore, map, class, locked),
+            settings_menu: None,
+            framebuffer_size: vec2(1.0, 1.0),
+            swipe: None,
+            menu_action: Rc::new(Cell::new(None)),
+            transition: None,
+            earned: None,
+            playback: None,
+            shared,
+            fade: Fade::fade_in(),
+            camera,
             ui_camera: Camera2d {
                 center: Vec2::ZERO,
                 rotation: 0.0,
                 fov: 1080.0,
             },
+        }
+    }
+
+    pub fn new_replay(
+        geng: &Geng,
+        assets: &Rc<Assets>,
+        replay: Replay,
+        speed: f32,
+        shared: Shared,
+    ) -> Self {
+        let mut state = Self::new(
+            geng,
+            assets,
+            replay.seed,
+            &replay.map,
+            &replay.class,
+            shared,
+        );
+        state.simulation = Simulation::new(
+            replay.highscore,
+            replay.seed,
+            &replay.map,
+            &replay.class,
+            &replay.locked,
+            &assets.rules,
+        );
+        state.playback = Some(Playback::new(replay, speed));
+        state
+    }
+}
+
+impl Simulation {
+    pub fn new(
+        highscore: Score,
+        seed: u64,
+        map: &str,
+        class: &str,
+        locked: &BTreeSet<Content>,
+        rules: &Rules,
+    ) -> Self {
+        let arena = rules
+            .maps
+            .get(map)
+            .unwrap_or_else(|| panic!("Unknown map {:?}", map))
+            .to_arena()
+            .expect("Invalid map");
+        let definition = rules
+            .classes
+            .get(class)
+            .unwrap_or_else(|| panic!("Unknown class {:?}", class));
+        let mut rng = StdRng::seed_from_u64(seed);
+        let player_attacks = rotate_randomly(
+            definition.attacks.iter().map(|id| rules.attacks.create(id)),
+            &mut rng,
+        )
+        .collect();
+        let potential_attacks = rotate_randomly(
+            definition
+                .potential
+                .iter()
+                .filter(|id| !locked.contains(&Content::Attack((*id).clone())))
+                .map(|id| rules.attacks.create(id)),
+            &mut rng,
+        )
+        .collect();
+        let new_attacks = definition.attack_slots - definition.attacks.len();
+        Self {
+            seed,
+            map: map.to_owned(),
+            class: class.to_owned(),
+            rng,
+            arena,
+            highscore,
+            score: 0,
+            move_time_limit: definition.move_time,
+            move_time_left: definition.move_time,
+            max_attack_slots: definition.attack_slots,
+            experience: Experience::new(rules.experience.clone()),
+            using_ultimate: None,
+            time_stop: 0,
+            decoy: None,
+            upgrade_menu: None,
+            freeze_move_timer: true,
+            spawns: vec![],
+            events: vec![],
             player: Player {
                 color: PLAYER_COLOR,
                 position: Vec2::ZERO,
                 interpolation: Interpolation::new(Vec2::ZERO),
+                slowed: false,
                 is_dead: false,
             },
             enemies: vec![],
             damages: vec![],
-            player_attacks: initial_attacks().collect(),
-            potential_attacks: potential_attacks().collect(),
-            player_ultimate: Teleport::new(4, 1),
-            upgrades: [
-                (
-                    UpgradeType::ReduceAttackCooldown,
-                    Upgrade::Attack {
-                        info: vec![UpgradeInfo::new(3)],
-                    },
-                ),
-                (
-                    UpgradeType::UpgradeAttack,
-                    Upgrade::Attack {
-                        info: vec![UpgradeInfo::new(2)],
-                    },
-                ),
-                (
-                    UpgradeType::NewAttack,
-                    Upgrade::Global {
-                        info: UpgradeInfo::new(3),
-                    },
-                ),
-                (
-                    UpgradeType::IncUltRadius,
-                    Upgrade::Global {
-                        info: UpgradeInfo::new(2),
-                    },
-                ),
-                (
-                    UpgradeType::ReduceUltCooldown,
-                    Upgrade::Global {
-                        info: UpgradeInfo::new(2),
-                    },
-                ),
-                (
-                    UpgradeType::IncDeathTimer,
-                    Upgrade::Global {
-                        info: UpgradeInfo::new(2),
-                    },
-                ),
-            ]
-            .into_iter()
-            .collect(),
-            spawn_prefabs: [
-                (
-                    EnemyType::Attacker,
-                    SpawnPrefab {
-                        movement: MovementType::Direct,
-                        min_score: 0,
-                        next_spawn: 1,
-                        color: Color::RED,
-                        cooldowns: [(0, 2.0), (1, 4.0), (2, 6.0), (3, 7.0)]
-                            .into_iter()
-                            .collect(),
-                        large_multiplier: 8.0,
-                        killed_siblings: 0,
-                    },
-                ),
-                (
-                    EnemyType::Frog,
-                    SpawnPrefab {
-                        movement: MovementType::SingleDouble {
-                            is_next_single: true,
+            player_attacks,
+            potential_attacks,
+            player_ultimate: Ultimate::new(definition.ultimate),
+            upgrades: definition
+                .upgrades
+                .iter()
+                .map(|&typ| {
+                    let upgrade = match typ {
+                        UpgradeType::ReduceAttackCooldown => Upgrade::Attack {
+                            info: vec![UpgradeInfo::new(3)],
+                        },
+                        UpgradeType::IncAttackDamage | UpgradeType::UpgradeAttack => {
+                            Upgrade::Attack {
+                                info: vec![UpgradeInfo::new(2)],
+                            }
+                        }
+                        UpgradeType::NewAttack => Upgrade::Global {
+                            info: UpgradeInfo::new(new_attacks),
                         },
-                        min_score: 10,
-                        next_spawn: 1,
-                        color: Color::GREEN,
-                        cooldowns: [(0, 6.0), (1, 12.0), (2, 12.0), (3, 18.0)]
-                            .into_iter()
-                            .collect(),
-                        large_multiplier: 20.0,
-                        killed_siblings: 0,
-                    },
-                ),
-                (
-                    EnemyType::King,
-                    SpawnPrefab {
-                        movement: MovementType::Neighbour,
-                        min_score: 60,
-                        next_spawn: 1,
-                        color: Color::MAGENTA,
-                        cooldowns: [(0, 6.0), (1, 10.0), (2, 15.0), (3, 15.0)]
-                            .into_iter()
-                            .collect(),
-                        large_multiplier: 18.0,
-                        killed_siblings: 0,
-                    },
-                ),
-            ]
-            .into_iter()
-            .collect(),
+                        _ => Upgrade::Global {
+                            info: UpgradeInfo::new(2),
+                        },
+                    };
+                    (typ, upgrade)
+                })
+                .collect(),
+            spawn_prefabs: rules
+                .enemies
+                .enemies
+                .iter()
+                .map(|(typ, definition)| {
+                    (typ.clone(), SpawnPrefab::new(definition, &rules.attacks))
+                })
+                .collect(),
+            locked_enemies: locked
+                .iter()
+                .filter_map(|content| match content {
+                    Content::Enemy(typ) => Some(typ.clone()),
+                    _ => None,
+                })
+                .collect(),
+            kills: BTreeMap::new(),
+            director: Director::new(&rules.waves),
         }
     }
 }
 
-fn rotate_randomly(attacks: impl IntoIterator<Item = Attack>) -> impl Iterator<Item = Attack> {
-    attacks.into_iter().map(|mut attack| {
-        let rotation = global_rng().gen_range(0..=3);
+fn rotate_randomly<'a>(
+    attacks: impl IntoIterator<Item = Attack> + 'a,
+    rng: &'a mut impl Rng,
+) -> impl Iterator<Item = Attack> + 'a {
+    attacks.into_iter().map(move |mut attack| {
+        let rotation = rng.gen_range(0..=3);
         for _ in 0..rotation {
             attack.rotate_left()
         }
         attack
     })
 }
-
-fn initial_attacks() -> impl Iterator<Item = Attack> {
-    rotate_randomly([Attack::new(
-        1,
-        [vec2(1, 0)],
-        Some(Attack::new(
-            2,
-            [vec2(1, 0), vec2(2, 0)],
-            Some(Attack::new(2, [vec2(1, 0), vec2(2, 0), vec2(3, 0)], None)),
-        )),
-    )])
-}
-
-fn potential_attacks() -> impl Iterator<Item = Attack> {
-    rotate_randomly([
-        Attack::new(
-            1,
-            [vec2(1, 0), vec2(2, 1)],
-            Some(Attack::new(
-                1,
-                [vec2(1, 0), vec2(2, 1), vec2(2, -1)],
-                Some(Attack::new(
-                    1,
-                    [vec2(1, 0), vec2(2, 1), vec2(2, -1), vec2(2, 0)],
-                    None,
-                )),
-            )),
-        ),
-        Attack::new(
-            2,
-            [vec2(1, 0), vec2(2, 0), vec2(1, 1)],
-            Some(Attack::new(
-                1,
-                [vec2(1, 0), vec2(2, 0), vec2(1, 1), vec2(1, -1)],
-                Some(Attack::new(
-                    1,
-                    [vec2(1, 0), vec2(2, 0), vec2(1, 1), vec2(1, -1), vec2(3, 1)],
-                    None,
-                )),
-            )),
-        ),
-        Attack::new(
-            3,
-            [vec2(1, 0), vec2(2, 0), vec2(3, 0), vec2(3, 1)],
-            Some(Attack::new(
-                3,
-                [vec2(1, 0), vec2(2, 0), vec2(3, 0), vec2(3, 1), vec2(3, -1)],
-                Some(Attack::new(
-                    3,
-                    [
-                        vec2(1, 0),
-                        vec2(2, 0),
-                        vec2(3, 0),
-                        vec2(3, 1),
-                        vec2(3, -1),
-                        vec2(4, 1),
-                        vec2(4, -1),
-                    ],
-                    None,
-                )),
-            )),
-        ),
-        Attack::new(
-            3,
-            [vec2(1, 0), vec2(2, 1), vec2(2, 0), vec2(2, -1)],
-            Some(Attack::new(
-                3,
-                [
-                    vec2(1, 0),
-                    vec2(2, 1),
-                    vec2(2, 0),
-                    vec2(2, -1),
-                    vec2(3, 1),
-                    vec2(3, -1),
-                ],
-                Some(Attack::new(
-                    3,
-                    [
-                        vec2(1, 0),
-                        vec2(2, 1),
-                        vec2(2, 0),
-                        vec2(2, -1),
-                        vec2(3, 1),
-                        vec2(3, -1),
-                        vec2(4, 0),
-                    ],
-                    None,
-                )),
-            )),
-        ),
-        Attack::new(
-            3,
-            [vec2(1, 1), vec2(1, -1), vec2(2, 0), vec2(3, 0)],
-            Some(Attack::new(
-                3,
-                [
-                    vec2(1, 1),
-                    vec2(1, -1),
-                    vec2(2, 0),
-                    vec2(3, 0),
-                    vec2(4, 1),
-                    vec2(4, -1),
-                ],
-                Some(Attack::new(
-                    3,
-                    [
-                        vec2(1, 1),
-                        vec2(1, -1),
-                        vec2(2, 0),
-                        vec2(3, 0),
-                        vec2(4, 1),
-                        vec2(4, -1),
-                        vec2(4, 0),
-                        vec2(5, 0),
-                    ],
-                    None,
-                )),
-            )),
-        ),
-    ])
-}
@@ -1,10 +1,35 @@
+mod arena;
+mod class_select;
+mod config;
 mod draw;
+mod game_over;
 mod init;
+mod pause;
+mod profile;
+mod replay;
+mod settings;
+mod title;
 
-use geng::Camera2d;
+use geng::{AbstractCamera2d, Camera2d};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, Ref, RefCell};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 
+use crate::audio::{Audio, MusicTrack};
+use crate::input::{Gamepad, Input, InputMap};
 use crate::logic::Interpolation;
 
+pub use arena::*;
+pub use class_select::*;
+pub use config::*;
+pub use game_over::*;
+pub use pause::*;
+pub use profile::*;
+pub use replay::*;
+pub use settings::*;
+pub use title::*;
+
 use super::*;
 
 use renderer::*;
@@ -12,6 +37,7 @@ use renderer::*;
 pub type Coord = i32;
 pub type Time = i32;
 pub type Score = u32;
+pub type Health = u32;
 pub type Position = Vec2<Coord>;
 
 pub const FADE_TIME: f32 = 2.0;
@@ -19,11 +45,20 @@ pub const PLAYER_ULTIMATE_ALPHA: f32 = 0.5;
 pub const INTERPOLATION_MAX_TIME: f32 = 0.2;
 pub const INTERPOLATION_MIN_SPEED: f32 = 5.0;
 pub const SLOTS_REQUIREMENTS: [Score; 4] = [0, 20, 90, 200];
-pub const LEVEL_SCORES: [Score; 11] = [3, 8, 15, 26, 40, 60, 85, 120, 170, 235, 300];
+pub const BONUS_SCORE: Score = 5;
+/// Shorter touches count as taps, in pixels.
+pub const SWIPE_MIN_DISTANCE: f32 = 50.0;
 
 // Things in world coordinates
 pub const TILE_SIZE: Vec2<f32> = vec2(1.0, 1.0);
 pub const WARNING_SIZE: Vec2<f32> = vec2(0.5, 0.5);
+pub const SPAWN_TURNS_SIZE: f32 = 0.4;
+pub const HEALTH_PIP_RADIUS: f32 = 0.06;
+pub const SHAPE_CUE_WIDTH: f32 = 0.04;
+pub const MODIFIER_OUTLINE_WIDTH: f32 = 0.05;
+pub const REGEN_TURNS: Time = 3;
+pub const EXPLOSION_DAMAGE: Health = 1;
+pub const ARMOR_WIDTH: f32 = 0.05;
 pub const UNIT_RADIUS: f32 = 0.25;
 pub const GRID_WIDTH: f32 = 0.05;
 pub const DAMAGE_WIDTH: f32 = 0.025;
@@ -31,6 +66,7 @@ pub const DAMAGE_EXTRA_SPACE: f32 = 0.25;
 
 // Things in screen coordinates
 pub const UPGRADE_SIZE: Vec2<f32> = vec2(200.0, 200.0);
+pub const CLASS_CARD_SIZE: Vec2<f32> = vec2(250.0, 400.0);
 pub const UPGRADE_EXTRA_SPACE: f32 = 50.0;
 pub const UPGRADE_FRAME_WIDTH: f32 = 1.0;
 pub const ATTACKS_OFFSET: f32 = 25.0;
@@ -48,14 +84,65 @@ pub const ATTACK_COOLDOWN_HEIGHT: f32 = 15.0;
 pub const LEVEL_OUTER_SPACE: f32 = 10.0;
 pub const LEVEL_INNER_SPACE: f32 = 7.5;
 pub const ATTACK_HIGHLIGHT_WIDTH: f32 = 2.0;
+pub const MENU_WIDTH: f32 = 500.0;
+pub const MENU_LINE_HEIGHT: f32 = 50.0;
 
 // Colors
 pub const ATTACK_HIGHLIGHT_COLOR: Color<f32> = Color::RED;
 pub const FADE_COLOR: Color<f32> = Color::BLACK;
+pub const BOSS_WAVE_COLOR: Color<f32> = Color {
+    r: 1.0,
+    g: 0.3,
+    b: 0.3,
+    a: 1.0,
+};
+pub const SHAPE_CUE_COLOR: Color<f32> = Color {
+    r: 0.0,
+    g: 0.0,
+    b: 0.0,
+    a: 0.6,
+};
+pub const GAME_OVER_TEXT_COLOR: Color<f32> = Color::WHITE;
 pub const PLAYER_COLOR: Color<f32> = Color::BLUE;
 pub const GRID_COLOR: Color<f32> = Color::GRAY;
 pub const WRAP_COLOR: Color<f32> = Color::CYAN;
+pub const WALL_COLOR: Color<f32> = Color {
+    r: 0.4,
+    g: 0.4,
+    b: 0.4,
+    a: 1.0,
+};
+pub const PIT_COLOR: Color<f32> = Color {
+    r: 0.15,
+    g: 0.05,
+    b: 0.05,
+    a: 1.0,
+};
+pub const SLOW_COLOR: Color<f32> = Color {
+    r: 0.2,
+    g: 0.3,
+    b: 0.6,
+    a: 0.5,
+};
+pub const BARRIER_COLOR: Color<f32> = Color {
+    r: 0.6,
+    g: 0.2,
+    b: 0.6,
+    a: 0.5,
+};
 pub const DAMAGE_COLOR: Color<f32> = Color::RED;
+pub const ENEMY_DAMAGE_COLOR: Color<f32> = Color {
+    r: 1.0,
+    g: 0.5,
+    b: 0.0,
+    a: 1.0,
+};
+pub const ENEMY_TELEGRAPH_COLOR: Color<f32> = Color {
+    r: 1.0,
+    g: 0.5,
+    b: 0.0,
+    a: 0.5,
+};
 pub const UPGRADE_FRAME_COLOR: Color<f32> = Color::GREEN;
 pub const UPGRADE_BACKGROUND_COLOR: Color<f32> = Color {
     r: 0.3,
@@ -125,6 +212,7 @@ pub struct Player {
     pub color: Color<f32>,
     pub position: Position,
     pub interpolation: Interpolation,
+    pub slowed: bool,
     pub is_dead: bool,
 }
 
@@ -135,56 +223,165 @@ pub struct Enemy {
     pub position: Position,
     pub interpolation: Interpolation,
     pub movement: MovementType,
+    pub pathfinding: bool,
+    pub attack: Option<Attack>,
+    pub telegraph: Vec<Position>,
+    pub slowed: bool,
+    pub health: Health,
+    pub max_health: Health,
+    pub armored: bool,
+    pub armor_used: bool,
+    /// Width and height in tiles, larger enemies extend up and right from `position`.
+    pub size: Coord,
+    pub reward: Score,
+    pub phases: Vec<EnemyPhase>,
+    pub phase: usize,
+    pub minions: Option<Minions>,
+    pub minion_timer: Time,
+    pub modifiers: Vec<Modifier>,
+    pub shield: bool,
+    pub regen_timer: Time,
+    pub is_boss: bool,
     pub is_dead: bool,
 }
 
-type Id = usize;
-
 #[derive(Debug, Clone)]
+pub struct EnemyPhase {
+    pub health: Health,
+    pub color: Option<Color<f32>>,
+    pub movement: Option<MovementType>,
+    pub attack: Option<Attack>,
+    pub minions: Option<Minions>,
+}
+
+pub type Id = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Caster {
     Player,
-    Enemy { id: Id },
+    Enemy {
+        id: Id,
+    },
+    /// An explosive enemy that died, which only hurts other enemies.
+    Explosion,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum MovementType {
     Direct,
     Neighbour,
-    SingleDouble { is_next_single: bool },
+    SingleDouble {
+        is_next_single: bool,
+    },
+    Knight,
+    Bishop,
+    /// Stands still for a turn, then jumps up to `range` tiles towards the target.
+    Teleporter {
+        range: Coord,
+        #[serde(default)]
+        charging: bool,
+    },
+    Orbiter {
+        radius: Coord,
+        #[serde(default)]
+        clockwise: bool,
+    },
+    /// Runs away while the target is closer than `distance`, approaches otherwise.
+    Flee {
+        distance: Coord,
+    },
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum EnemyType {
-    Attacker,
-    Frog,
-    King,
-}
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct EnemyType(pub String);
 
 #[derive(Debug, Clone)]
 pub struct SpawnPrefab {
     pub movement: MovementType,
+    pub pathfinding: bool,
     pub min_score: Score,
-    pub next_spawn: Time,
     pub color: Color<f32>,
-    pub cooldowns: HashMap<usize, f32>,
-    pub large_multiplier: f32,
-    pub killed_siblings: usize,
+    pub attack: Option<Attack>,
+    pub health: Health,
+    pub armored: bool,
+    pub size: Coord,
+    pub reward: Score,
+    pub phases: Vec<EnemyPhase>,
+    pub minions: Option<Minions>,
+    pub cost: Option<f32>,
+    pub telegraph: Time,
+    pub spawn: SpawnStrategy,
+    pub blocked: BlockedSpawn,
+}
+
+pub struct Spawn {
+    pub position: Position,
+    pub turns_left: Time,
+    pub strategy: SpawnStrategy,
+    pub blocked: BlockedSpawn,
+    pub enemy: Enemy,
+}
+
+pub struct Director {
+    pub turn: Time,
+    pub budget: f32,
+    pub pacing: Pacing,
+    pub elites: EliteConfig,
+    pub waves: VecDeque<WaveDefinition>,
 }
 
+#[derive(Debug, Clone)]
 pub struct Action {
     pub cooldown: Time,
     pub next: Time,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AttackId(pub String);
+
+#[derive(Debug, Clone)]
 pub struct Attack {
+    pub id: AttackId,
+    pub name: String,
     pub action: Action,
+    pub damage: Health,
     pub pattern: Vec<Position>,
     pub upgrade: Option<Box<Attack>>,
 }
 
-pub struct Teleport {
-    pub action: Action,
-    pub radius: Coord,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UltimateType {
+    Teleport,
+    TimeStop,
+    Shockwave,
+    Swap,
+    Decoy,
+}
+
+#[derive(Debug, Clone)]
+pub enum Ultimate {
+    /// Free movement within the radius, while the move timer drains at half speed.
+    Teleport { action: Action, radius: Coord },
+    /// Enemies and spawns are frozen for a number of turns.
+    TimeStop { action: Action, turns: Time },
+    /// Damages every tile around the player.
+    Shockwave {
+        action: Action,
+        radius: Coord,
+        damage: Health,
+    },
+    /// Swaps places with the closest enemy within the radius.
+    Swap { action: Action, radius: Coord },
+    /// Leaves a decoy that enemies target instead of the player.
+    Decoy { action: Action, turns: Time },
+}
+
+#[derive(Debug, Clone)]
+pub struct Decoy {
+    pub position: Position,
+    pub turns_left: Time,
 }
 
 pub struct UpgradeInfo {
@@ -203,14 +400,18 @@ pub enum Upgrade {
     Attack { info: Vec<UpgradeInfo> },
 }
 
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum UpgradeType {
     NewAttack,
     IncUltRadius,
+    IncUltDuration,
+    IncUltDamage,
     ReduceUltCooldown,
     IncDeathTimer,
     ReduceAttackCooldown,
+    IncAttackDamage,
     UpgradeAttack,
+    BonusScore,
 }
 
 pub struct UpgradeMenu {
@@ -223,6 +424,7 @@ pub struct Experience {
     pub level: u32,
     pub exp: Score,
     pub exp_to_next_lvl: Score,
+    pub curve: ExperienceCurve,
 }
 
 pub struct Fade {
@@ -232,71 +434,146 @@ pub struct Fade {
     pub speed: f32,
 }
 
-pub struct GameState {
-    pub geng: Geng,
-    pub assets: Rc<Assets>,
-    pub camera: Camera2d,
-    pub ui_camera: Camera2d,
-    pub arena_bounds: AABB<Coord>,
-    pub highscore: AutoSave<Score>,
+pub struct Simulation {
+    pub seed: u64,
+    pub map: String,
+    pub class: String,
+    pub rng: StdRng,
+    pub arena: Arena,
+    pub highscore: Score,
     pub score: Score,
     pub experience: Experience,
     pub move_time_limit: f32,
     pub move_time_left: f32,
     pub freeze_move_timer: bool,
+    pub max_attack_slots: usize,
     pub player_attacks: Vec<Attack>,
     pub potential_attacks: Vec<Attack>,
-    pub player_ultimate: Teleport,
+    pub player_ultimate: Ultimate,
     pub using_ultimate: Option<Position>,
+    pub time_stop: Time,
+    pub decoy: Option<Decoy>,
     pub player: Player,
     pub enemies: Vec<Enemy>,
-    pub damages: Vec<Position>,
-    pub spawns: Vec<(Position, Enemy)>,
-    pub spawn_prefabs: HashMap<EnemyType, SpawnPrefab>,
-    pub upgrades: HashMap<UpgradeType, Upgrade>,
+    pub damages: Vec<(Caster, Position)>,
+    pub spawns: Vec<Spawn>,
+    pub spawn_prefabs: BTreeMap<EnemyType, SpawnPrefab>,
+    /// Enemies the director does not spawn on its own.
+    pub locked_enemies: BTreeSet<EnemyType>,
+    pub kills: BTreeMap<EnemyType, u32>,
+    pub director: Director,
+    pub upgrades: BTreeMap<UpgradeType, Upgrade>,
     pub upgrade_menu: Option<UpgradeMenu>,
+    pub events: Vec<Event>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundType {
+    Hit,
+    Death,
+    Movement,
+    Blip,
+    Select,
+    Upgrade,
+}
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    Sound(SoundType),
+}
+
+/// Handles that live for the whole session and are passed between states.
+#[derive(Clone)]
+pub struct Shared {
+    pub audio: Rc<Audio>,
+    pub gamepad: Rc<RefCell<Gamepad>>,
+    pub saves: Rc<RefCell<Saves>>,
+}
+
+pub struct GameState {
+    pub geng: Geng,
+    pub assets: Rc<Assets>,
+    pub camera: Camera2d,
+    pub ui_camera: Camera2d,
+    /// Currency earned by the finished run, once it is recorded in the profile.
+    pub earned: Option<u32>,
+    pub simulation: Simulation,
+    pub time: f32,
+    pub recording: Replay,
+    pub playback: Option<Playback>,
     pub fade: Fade,
-    pub sounds: Vec<geng::SoundEffect>,
-    pub volume: f64,
+    pub shared: Shared,
+    pub settings_menu: Option<SettingsMenu>,
+    pub framebuffer_size: Vec2<f32>,
+    pub swipe: Option<Swipe>,
+    /// Set by the pause and game over states when they are closed.
+    pub menu_action: Rc<Cell<Option<MenuAction>>>,
+    pub transition: Option<geng::Transition>,
+}
+
+/// Start and current position of a touch, in screen coordinates.
+pub struct Swipe {
+    pub start: Vec2<f32>,
+    pub end: Vec2<f32>,
 }
 
 impl geng::State for GameState {
     fn update(&mut self, delta_time: f64) {
         let delta_time = delta_time as f32;
-
-        // Interpolate player and enemies
-        self.player.interpolation.update(delta_time);
-        for enemy in &mut self.enemies {
-            enemy.interpolation.update(delta_time);
+        self.shared.audio.update(delta_time);
+        if let Some(action) = self.menu_action.take() {
+            self.handle_menu_action(action);
+        }
+        let inputs = self.shared.gamepad.borrow_mut().poll();
+        for input in inputs {
+            self.handle_input(input);
+        }
+        if self.settings_menu.is_some() {
+            return;
         }
 
         // Fade
         self.fade.current =
             (self.fade.current + self.fade.speed * delta_time).clamp(self.fade.min, self.fade.max);
-        if self.fade.current == self.fade.max {
-            self.reset();
+        if self.simulation.player.is_dead
+            && self.fade.current == self.fade.max
+            && self.transition.is_none()
+        {
+            self.game_over();
         }
 
-        if self.player.is_dead {
+        if self.simulation.player.is_dead {
             self.fade.speed = self.fade.speed.abs();
-            return;
         }
 
-        // Player move limit
-        if self.upgrade_menu.is_none() {
-            if !self.freeze_move_timer {
-                self.move_time_left -= delta_time
-                    * if self.using_ultimate.is_some() {
-                        0.5
-                    } else {
-                        1.0
-                    };
+        let interpolation_time = self.settings().interpolation_time;
+        if let Some(playback) = &mut self.playback {
+            let delta_time = delta_time * playback.speed;
+            for ReplayCommand {
+                move_time_left,
+                command,
+                ..
+            } in playback.update(delta_time)
+            {
+                // The director reacts to the move timer, which playback does not run
+                self.simulation.move_time_left = move_time_left;
+                self.command(command);
             }
-            if self.move_time_left <= 0.0 {
-                self.move_time_left = 0.0;
-                self.kill_player();
+            self.simulation
+                .update_interpolation(delta_time, interpolation_time);
+        } else {
+            self.time += delta_time;
+            let was_dead = self.simulation.player.is_dead;
+            self.simulation
+                .update_interpolation(delta_time, interpolation_time);
+            self.simulation.update_move_timer(delta_time);
+            if !was_dead && self.simulation.player.is_dead {
+                // Ran out of time, which the replay reproduces as a restart
+                self.recording
+                    .record(self.time, self.simulation.move_time_left, Command::Restart);
             }
         }
+        self.handle_events();
     }
 
     fn draw(&mut self, framebuffer: &mut ugli::Framebuffer) {
@@ -304,37 +581,249 @@ impl geng::State for GameState {
         self.draw(framebuffer);
     }
 
+    fn transition(&mut self) -> Option<geng::Transition> {
+        self.transition.take()
+    }
+
     fn handle_event(&mut self, event: geng::Event) {
         match event {
-            geng::Event::KeyDown { key } => match key {
-                geng::Key::Left => {
-                    self.tick(vec2(-1, 0));
-                }
-                geng::Key::Right => {
-                    self.tick(vec2(1, 0));
-                }
-                geng::Key::Down => {
-                    self.tick(vec2(0, -1));
-                }
-                geng::Key::Up => {
-                    self.tick(vec2(0, 1));
-                }
-                geng::Key::Space => {
-                    self.use_ultimate();
-                }
-                geng::Key::Enter => {
-                    self.select_upgrade();
+            geng::Event::KeyDown { key } => self.handle_input(Input::Key(key)),
+            geng::Event::MouseDown {
+                position,
+                button: geng::MouseButton::Left,
+            } => self.click(position.map(|x| x as f32)),
+            geng::Event::TouchStart { touches } => {
+                self.swipe = touches.first().map(|touch| {
+                    let position = touch.position.map(|x| x as f32);
+                    Swipe {
+                        start: position,
+                        end: position,
+                    }
+                });
+            }
+            geng::Event::TouchMove { touches } => {
+                if let (Some(swipe), Some(touch)) = (&mut self.swipe, touches.first()) {
+                    swipe.end = touch.position.map(|x| x as f32);
                 }
-                geng::Key::R => {
-                    self.kill_player();
+            }
+            geng::Event::TouchEnd { .. } => {
+                if let Some(swipe) = self.swipe.take() {
+                    let delta = swipe.end - swipe.start;
+                    if delta.len() < SWIPE_MIN_DISTANCE {
+                        self.click(swipe.start);
+                    } else if delta.x.abs() > delta.y.abs() {
+                        self.pointer_command(Command::Move(vec2(delta.x.signum() as Coord, 0)));
+                    } else {
+                        self.pointer_command(Command::Move(vec2(0, delta.y.signum() as Coord)));
+                    }
                 }
-                _ => {}
-            },
+            }
             _ => {}
         }
     }
 }
 
+impl GameState {
+    fn handle_input(&mut self, input: Input) {
+        if let Some(menu) = &mut self.settings_menu {
+            if menu.handle_input(
+                input,
+                &mut self.shared.saves.borrow_mut().settings,
+                &self.shared.audio,
+            ) {
+                self.settings_menu = None;
+            }
+            return;
+        }
+        if self.settings().controls.is_menu(input) {
+            if !self.simulation.player.is_dead {
+                self.pause();
+            }
+            return;
+        }
+
+        let command = self.settings().controls.command(input);
+        let command = match command {
+            Some(command) => command,
+            None => return,
+        };
+        if let Some(playback) = &mut self.playback {
+            match command {
+                Command::Move(direction) if direction.y > 0 => playback.speed_up(),
+                Command::Move(direction) if direction.y < 0 => playback.slow_down(),
+                _ => {}
+            }
+            return;
+        }
+        self.command(command);
+    }
+
+    fn pause(&mut self) {
+        let pause = Pause::new(
+            &self.geng,
+            &self.assets,
+            self.shared.clone(),
+            self.settings().controls.clone(),
+            self.menu_action.clone(),
+        );
+        self.shared.audio.play(SoundType::Select);
+        self.transition = Some(geng::Transition::Push(Box::new(pause)));
+    }
+
+    fn game_over(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.save_replay();
+        let simulation = &self.simulation;
+        // The simulation raises its high score along with the score
+        let highscore = match &self.playback {
+            Some(playback) => playback.replay.highscore,
+            None => self.recording.highscore,
+        };
+        let summary = RunSummary {
+            seed: simulation.seed,
+            score: simulation.score,
+            highscore,
+            level: simulation.experience.level,
+            kills: simulation.kills.clone(),
+            earned: self.earned,
+            currency: self.shared.saves.borrow().profile.currency,
+        };
+        let game_over = GameOver::new(
+            &self.geng,
+            &self.assets,
+            self.shared.clone(),
+            self.settings().controls.clone(),
+            summary,
+            self.menu_action.clone(),
+        );
+        self.transition = Some(geng::Transition::Push(Box::new(game_over)));
+    }
+
+    fn handle_menu_action(&mut self, action: MenuAction) {
+        match action {
+            MenuAction::Resume => {}
+            MenuAction::Settings => self.settings_menu = Some(SettingsMenu::default()),
+            MenuAction::GiveUp => self.command(Command::Restart),
+            MenuAction::Restart => self.reset(),
+            MenuAction::MainMenu => {
+                let title = TitleScreen::new(
+                    &self.geng,
+                    &self.assets,
+                    global_rng().gen(),
+                    &self.simulation.map,
+                    self.shared.clone(),
+                );
+                self.transition = Some(geng::Transition::Switch(Box::new(title)));
+            }
+        }
+    }
+
+    fn settings(&self) -> Ref<Settings> {
+        Ref::map(self.shared.saves.borrow(), |saves| &*saves.settings)
+    }
+
+    fn click(&mut self, position: Vec2<f32>) {
+        if let Some(upgrade_menu) = &self.simulation.upgrade_menu {
+            let position = self
+                .ui_camera
+                .screen_to_world(self.framebuffer_size, position);
+            let screen_size = vec2(
+                self.ui_camera.fov / self.framebuffer_size.y * self.framebuffer_size.x,
+                self.ui_camera.fov,
+            );
+            if let Some(choice) = upgrade_menu
+                .card_aabbs(screen_size)
+                .iter()
+                .position(|aabb| aabb.contains(position))
+            {
+                self.pointer_command(Command::Choose(choice));
+            }
+            return;
+        }
+
+        let position = self.camera.screen_to_world(self.framebuffer_size, position) / TILE_SIZE;
+        let cell = position.map(|x| x.round() as Coord);
+        if self.simulation.using_ultimate.is_some() {
+            self.pointer_command(Command::Target(cell));
+            return;
+        }
+        let delta = cell - self.simulation.player.position;
+        if delta.x.abs() + delta.y.abs() == 1 {
+            self.pointer_command(Command::Move(delta));
+        }
+    }
+
+    /// Commands from the mouse or touch, which only work outside of menus and replays.
+    fn pointer_command(&mut self, command: Command) {
+        if self.settings_menu.is_none() && self.playback.is_none() {
+            self.command(command);
+        }
+    }
+
+    pub fn command(&mut self, command: Command) {
+        if self.playback.is_none() {
+            self.recording
+                .record(self.time, self.simulation.move_time_left, command);
+        }
+        match command {
+            Command::Move(player_move) => self.tick(player_move),
+            Command::Ultimate => self.use_ultimate(),
+            Command::Confirm => self.select_upgrade(),
+            Command::Restart => self.kill_player(),
+            Command::Choose(choice) => self.choose_upgrade(choice),
+            Command::Target(target) => self.teleport(target),
+        }
+    }
+
+    pub fn tick(&mut self, player_move: Position) {
+        self.simulation.tick(player_move);
+        self.handle_events();
+    }
+
+    pub fn use_ultimate(&mut self) {
+        self.simulation.use_ultimate();
+        self.handle_events();
+    }
+
+    pub fn select_upgrade(&mut self) {
+        self.simulation.select_upgrade();
+        self.handle_events();
+    }
+
+    pub fn choose_upgrade(&mut self, choice: usize) {
+        self.simulation.choose_upgrade(choice);
+        self.handle_events();
+    }
+
+    pub fn teleport(&mut self, target: Position) {
+        self.simulation.teleport(target);
+        self.handle_events();
+    }
+
+    pub fn kill_player(&mut self) {
+        self.simulation.kill_player();
+        self.handle_events();
+    }
+
+    fn handle_events(&mut self) {
+        for event in std::mem::take(&mut self.simulation.events) {
+            match event {
+                Event::Sound(sound) => self.shared.audio.play(sound),
+            }
+        }
+
+        if self.playback.is_none() && self.simulation.player.is_dead && self.earned.is_none() {
+            let earned = self
+                .shared
+                .saves
+                .borrow_mut()
+                .profile
+                .record_run(&self.simulation, &self.assets.rules);
+            self.earned = Some(earned);
+        }
+    }
+}
+
 impl MovementType {
     pub fn move_towards(&mut self, target: Position) -> Position {
         match self {
@@ -355,34 +844,315 @@ impl MovementType {
                 *is_next_single = !*is_next_single;
                 delta
             }
+            Self::Knight => {
+                let distance = |delta: Position| {
+                    let left = target - delta;
+                    left.x.abs() + left.y.abs()
+                };
+                self.steps()
+                    .into_iter()
+                    .min_by_key(|&delta| distance(delta))
+                    .filter(|&delta| distance(delta) < distance(Vec2::ZERO))
+                    .unwrap_or(Vec2::ZERO)
+            }
+            Self::Bishop => {
+                let sign = |x: Coord| if x == 0 { 1 } else { x.signum() };
+                if target == Vec2::ZERO {
+                    Vec2::ZERO
+                } else {
+                    vec2(sign(target.x), sign(target.y))
+                }
+            }
+            Self::Teleporter { range, charging } => {
+                *charging = !*charging;
+                if *charging {
+                    return Vec2::ZERO;
+                }
+                if target.x.abs().max(target.y.abs()) <= *range {
+                    // Land next to the target
+                    target - Self::Direct.move_towards(target)
+                } else {
+                    target.map(|x| x.clamp(-*range, *range))
+                }
+            }
+            Self::Orbiter { radius, clockwise } => {
+                let distance = target.x.abs().max(target.y.abs());
+                if distance > *radius {
+                    Self::Neighbour.move_towards(target)
+                } else if distance < *radius {
+                    Self::Neighbour.move_towards(-target)
+                } else {
+                    // Step around the ring, turning as far as possible in the orbit direction
+                    let offset = -target;
+                    let on_ring = |delta: Position| {
+                        let next = offset + delta;
+                        next.x.abs().max(next.y.abs()) == *radius
+                    };
+                    let turn = |delta: Position| {
+                        let cross = offset.x * delta.y - offset.y * delta.x;
+                        if *clockwise {
+                            -cross
+                        } else {
+                            cross
+                        }
+                    };
+                    Self::Neighbour
+                        .steps()
+                        .into_iter()
+                        .filter(|&delta| on_ring(delta))
+                        .max_by_key(|&delta| turn(delta))
+                        .unwrap_or(Vec2::ZERO)
+                }
+            }
+            Self::Flee { distance } => {
+                if target.x.abs().max(target.y.abs()) < *distance {
+                    Self::Neighbour.move_towards(-target)
+                } else {
+                    Self::Direct.move_towards(target)
+                }
+            }
+        }
+    }
+
+    /// Whether every move brings the unit closer to its target.
+    pub fn approaches(&self) -> bool {
+        !matches!(
+            self,
+            Self::Teleporter { .. } | Self::Orbiter { .. } | Self::Flee { .. }
+        )
+    }
+
+    pub fn steps(&self) -> Vec<Position> {
+        let directions = [vec2(1, 0), vec2(-1, 0), vec2(0, 1), vec2(0, -1)];
+        match self {
+            Self::Direct => directions.to_vec(),
+            Self::Neighbour => (-1..=1)
+                .flat_map(|x| (-1..=1).map(move |y| vec2(x, y)))
+                .filter(|&delta| delta != Vec2::ZERO)
+                .collect(),
+            Self::SingleDouble { is_next_single } => {
+                let length = if *is_next_single { 1 } else { 2 };
+                directions.iter().map(|&dir| dir * length).collect()
+            }
+            Self::Knight => [
+                vec2(1, 2),
+                vec2(2, 1),
+                vec2(2, -1),
+                vec2(1, -2),
+                vec2(-1, -2),
+                vec2(-2, -1),
+                vec2(-2, 1),
+                vec2(-1, 2),
+            ]
+            .to_vec(),
+            Self::Bishop => vec![vec2(1, 1), vec2(1, -1), vec2(-1, 1), vec2(-1, -1)],
+            Self::Teleporter { .. } => vec![],
+            Self::Orbiter { .. } | Self::Flee { .. } => Self::Neighbour.steps(),
         }
     }
+
+    pub fn advance(&mut self) {
+        match self {
+            Self::SingleDouble { is_next_single } => *is_next_single = !*is_next_single,
+            Self::Teleporter { charging, .. } => *charging = !*charging,
+            _ => {}
+        }
+    }
+
+    pub fn advanced(&self) -> Self {
+        let mut movement = self.clone();
+        movement.advance();
+        movement
+    }
 }
 
 impl Enemy {
+    pub fn take_hit(&mut self, damage: Health) {
+        if self.shield {
+            self.shield = false;
+            return;
+        }
+        if self.armored && !self.armor_used {
+            self.armor_used = true;
+            return;
+        }
+        self.health = self.health.saturating_sub(damage);
+        if self.health == 0 {
+            self.is_dead = true;
+            return;
+        }
+
+        while let Some(phase) = self.phases.get(self.phase) {
+            if self.health > phase.health {
+                break;
+            }
+            if let Some(color) = phase.color {
+                self.color = color;
+            }
+            if let Some(movement) = &phase.movement {
+                self.movement = movement.clone();
+            }
+            if let Some(attack) = &phase.attack {
+                self.attack = Some(attack.clone());
+                self.telegraph.clear();
+            }
+            if let Some(minions) = &phase.minions {
+                self.minion_timer = minions.every;
+                self.minions = Some(minions.clone());
+            }
+            self.phase += 1;
+        }
+    }
+
+    pub fn add_modifier(&mut self, modifier: Modifier) {
+        match modifier {
+            Modifier::Shielded => self.shield = true,
+            Modifier::Regenerating => {
+                self.max_health += 1;
+                self.health += 1;
+                self.regen_timer = REGEN_TURNS;
+            }
+            _ => {}
+        }
+        self.modifiers.push(modifier);
+    }
+
+    pub fn has_modifier(&self, modifier: Modifier) -> bool {
+        self.modifiers.contains(&modifier)
+    }
+
+    pub fn regenerate(&mut self) {
+        if !self.has_modifier(Modifier::Regenerating) || self.health >= self.max_health {
+            return;
+        }
+        self.regen_timer -= 1;
+        if self.regen_timer <= 0 {
+            self.health += 1;
+            self.regen_timer = REGEN_TURNS;
+        }
+    }
+
+    pub fn tiles(&self) -> impl Iterator<Item = Position> {
+        logic::footprint(self.position, self.size)
+    }
+
+    pub fn occupies(&self, pos: Position) -> bool {
+        let delta = pos - self.position;
+        (0..self.size).contains(&delta.x) && (0..self.size).contains(&delta.y)
+    }
+
     pub fn move_towards(&mut self, delta: Position) -> Position {
-        let pos = self.position + self.movement.move_towards(delta);
-        if let MovementType::SingleDouble { is_next_single } = &self.movement {
-            self.color = if *is_next_single {
-                Color::GREEN
-            } else {
-                Color::rgb(0.0, 0.5, 0.0)
-            };
+        self.position + self.movement.move_towards(delta)
+    }
+
+    pub fn current_color(&self) -> Color<f32> {
+        match self.movement {
+            MovementType::SingleDouble {
+                is_next_single: false,
+            } => Color::rgba(
+                self.color.r * 0.5,
+                self.color.g * 0.5,
+                self.color.b * 0.5,
+                self.color.a,
+            ),
+            _ => self.color,
+        }
+    }
+}
+
+impl Modifier {
+    pub fn color(&self) -> Color<f32> {
+        match self {
+            Self::Shielded => Color::CYAN,
+            Self::Hasty => Color::YELLOW,
+            Self::Splitting => Color::GREEN,
+            Self::Explosive => Color::RED,
+            Self::Regenerating => Color::MAGENTA,
         }
-        pos
     }
 }
 
 impl SpawnPrefab {
-    pub fn refresh_cooldown(&mut self, siblings: usize) {
-        let killed_multiplier = 1.0 - self.killed_siblings as f32 * 0.05;
-        let cooldown = self
-            .cooldowns
-            .get(&siblings)
-            .copied()
-            .unwrap_or(self.large_multiplier)
-            * killed_multiplier;
-        self.next_spawn = cooldown.ceil() as _;
+    pub fn new(definition: &EnemyDefinition, attacks: &AttackLibrary) -> Self {
+        Self {
+            movement: definition.movement.clone(),
+            pathfinding: definition.pathfinding,
+            min_score: definition.min_score,
+            color: definition.color,
+            attack: definition.attack.as_ref().map(|id| attacks.create(id)),
+            health: definition.health,
+            armored: definition.armored,
+            size: definition.size,
+            reward: definition.reward,
+            phases: definition
+                .phases
+                .iter()
+                .map(|phase| EnemyPhase {
+                    health: phase.health,
+                    color: phase.color,
+                    movement: phase.movement.clone(),
+                    attack: phase.attack.as_ref().map(|id| attacks.create(id)),
+                    minions: phase.minions.clone(),
+                })
+                .collect(),
+            minions: definition.minions.clone(),
+            cost: definition.cost,
+            telegraph: definition.telegraph,
+            spawn: definition.spawn,
+            blocked: definition.blocked,
+        }
+    }
+
+    pub fn enemy(&self, typ: &EnemyType, position: Position, is_boss: bool) -> Enemy {
+        Enemy {
+            typ: typ.clone(),
+            color: self.color,
+            position,
+            interpolation: Interpolation::new(position.map(|x| x as f32)),
+            movement: self.movement.clone(),
+            pathfinding: self.pathfinding,
+            attack: self.attack.clone(),
+            telegraph: vec![],
+            slowed: false,
+            health: self.health,
+            max_health: self.health,
+            armored: self.armored,
+            armor_used: false,
+            size: self.size,
+            reward: self.reward,
+            phases: self.phases.clone(),
+            phase: 0,
+            minions: self.minions.clone(),
+            minion_timer: self.minions.as_ref().map_or(0, |minions| minions.every),
+            modifiers: vec![],
+            shield: false,
+            regen_timer: 0,
+            is_boss,
+            is_dead: false,
+        }
+    }
+}
+
+impl Director {
+    pub fn new(config: &WaveConfig) -> Self {
+        Self {
+            turn: 0,
+            budget: config.pacing.initial_budget,
+            pacing: config.pacing.clone(),
+            elites: config.elites.clone(),
+            waves: config.waves.iter().cloned().collect(),
+        }
+    }
+}
+
+impl Fade {
+    pub fn fade_in() -> Self {
+        Self {
+            min: 0.0,
+            max: 1.0,
+            current: 1.0,
+            speed: -1.0 / FADE_TIME,
+        }
     }
 }
 
@@ -410,12 +1180,18 @@ impl Action {
 
 impl Attack {
     pub fn new(
+        id: AttackId,
+        name: String,
         cooldown: Time,
+        damage: Health,
         pattern: impl IntoIterator<Item = Position>,
         upgrade: Option<Attack>,
     ) -> Self {
         Self {
+            id,
+            name,
             action: Action::new(cooldown),
+            damage,
             pattern: pattern.into_iter().collect(),
             upgrade: upgrade.map(|attack| Box::new(attack)),
         }
@@ -434,30 +1210,150 @@ impl Attack {
         self.pattern.iter().map(move |delta| caster_pos + *delta)
     }
 
+    pub fn directed_positions(&self, caster_pos: Position, direction: Position) -> Vec<Position> {
+        let rotations = if direction.x.abs() >= direction.y.abs() {
+            if direction.x >= 0 {
+                0
+            } else {
+                2
+            }
+        } else if direction.y > 0 {
+            1
+        } else {
+            3
+        };
+        self.pattern
+            .iter()
+            .map(|&delta| {
+                let mut delta = delta;
+                for _ in 0..rotations {
+                    delta = vec2(-delta.y, delta.x);
+                }
+                caster_pos + delta
+            })
+            .collect()
+    }
+
     pub fn upgrade(&mut self) {
         if let Some(mut attack) = self.upgrade.take() {
             attack.action.cooldown = self.action.cooldown;
+            attack.damage = attack.damage.max(self.damage);
             *self = *attack;
         }
     }
 }
 
-impl Teleport {
-    pub fn new(cooldown: Time, radius: Coord) -> Self {
-        Self {
-            action: Action::new(cooldown),
-            radius,
+impl Ultimate {
+    pub fn new(typ: UltimateType) -> Self {
+        match typ {
+            UltimateType::Teleport => Self::Teleport {
+                action: Action::new(4),
+                radius: 1,
+            },
+            UltimateType::TimeStop => Self::TimeStop {
+                action: Action::new(8),
+                turns: 2,
+            },
+            UltimateType::Shockwave => Self::Shockwave {
+                action: Action::new(6),
+                radius: 1,
+                damage: 1,
+            },
+            UltimateType::Swap => Self::Swap {
+                action: Action::new(5),
+                radius: 2,
+            },
+            UltimateType::Decoy => Self::Decoy {
+                action: Action::new(7),
+                turns: 3,
+            },
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Teleport { .. } => "Teleport",
+            Self::TimeStop { .. } => "Time stop",
+            Self::Shockwave { .. } => "Shockwave",
+            Self::Swap { .. } => "Swap",
+            Self::Decoy { .. } => "Decoy",
+        }
+    }
+
+    pub fn action(&self) -> &Action {
+        match self {
+            Self::Teleport { action, .. }
+            | Self::TimeStop { action, .. }
+            | Self::Shockwave { action, .. }
+            | Self::Swap { action, .. }
+            | Self::Decoy { action, .. } => action,
+        }
+    }
+
+    pub fn action_mut(&mut self) -> &mut Action {
+        match self {
+            Self::Teleport { action, .. }
+            | Self::TimeStop { action, .. }
+            | Self::Shockwave { action, .. }
+            | Self::Swap { action, .. }
+            | Self::Decoy { action, .. } => action,
+        }
+    }
+
+    pub fn radius(&self) -> Option<Coord> {
+        match self {
+            Self::Teleport { radius, .. }
+            | Self::Shockwave { radius, .. }
+            | Self::Swap { radius, .. } => Some(*radius),
+            Self::TimeStop { .. } | Self::Decoy { .. } => None,
+        }
+    }
+
+    pub fn turns(&self) -> Option<Time> {
+        match self {
+            Self::TimeStop { turns, .. } | Self::Decoy { turns, .. } => Some(*turns),
+            _ => None,
         }
     }
 
+    pub fn upgrades(&self) -> Vec<UpgradeType> {
+        let mut upgrades = vec![UpgradeType::ReduceUltCooldown];
+        if self.radius().is_some() {
+            upgrades.push(UpgradeType::IncUltRadius);
+        }
+        if self.turns().is_some() {
+            upgrades.push(UpgradeType::IncUltDuration);
+        }
+        if let Self::Shockwave { .. } = self {
+            upgrades.push(UpgradeType::IncUltDamage);
+        }
+        upgrades
+    }
+
     pub fn boundary(&self) -> AABB<Coord> {
-        AABB::ZERO.extend_uniform(self.radius)
+        AABB::ZERO.extend_uniform(self.radius().unwrap_or(0))
+    }
+
+    pub fn deltas(&self) -> impl Iterator<Item = Position> {
+        let radius = self.radius().unwrap_or(0);
+        (-radius..=radius).flat_map(move |x| (-radius..=radius).map(move |y| vec2(x, y)))
     }
+}
 
-    pub fn deltas(&self) -> impl Iterator<Item = Position> + '_ {
-        (-self.radius..=self.radius)
-            .flat_map(|x| (-self.radius..=self.radius).map(move |y| vec2(x, y)))
-            .filter(|pos| pos.x.abs() <= self.radius && pos.y.abs() <= self.radius)
+impl UpgradeMenu {
+    /// Where the option cards are drawn, in UI coordinates.
+    pub fn card_aabbs(&self, screen_size: Vec2<f32>) -> Vec<AABB<f32>> {
+        let upgrades_width = (UPGRADE_SIZE.x + UPGRADE_EXTRA_SPACE) * self.options.len() as f32
+            - UPGRADE_EXTRA_SPACE;
+        let upgrade_aabb = AABB::ZERO.extend_symmetric(UPGRADE_SIZE / 2.0);
+        let left_pos = screen_size / 2.0 - vec2((upgrades_width - UPGRADE_SIZE.x) / 2.0, 0.0);
+        (0..self.options.len())
+            .map(|i| {
+                upgrade_aabb.translate(
+                    left_pos + i as f32 * vec2(UPGRADE_SIZE.x + UPGRADE_EXTRA_SPACE, 0.0),
+                )
+            })
+            .collect()
     }
 }
 
@@ -471,11 +1367,12 @@ impl UpgradeInfo {
 }
 
 impl Experience {
-    pub fn new() -> Self {
+    pub fn new(curve: ExperienceCurve) -> Self {
         Self {
             level: 0,
             exp: 0,
-            exp_to_next_lvl: LEVEL_SCORES[0],
+            exp_to_next_lvl: curve.level_score(0),
+            curve,
         }
     }
 
@@ -486,7 +1383,7 @@ impl Experience {
             // self.exp -= self.exp_to_next_lvl;
             lvl_ups += 1;
             self.level += 1;
-            self.exp_to_next_lvl = LEVEL_SCORES[self.level as usize];
+            self.exp_to_next_lvl = self.curve.level_score(self.level);
         }
         lvl_ups
     }
@@ -495,7 +1392,7 @@ impl Experience {
         if self.level <= 0 {
             self.exp as f32 / self.exp_to_next_lvl as f32
         } else {
-            let last_up = LEVEL_SCORES[self.level as usize - 1];
+            let last_up = self.curve.level_score(self.level - 1);
             (self.exp - last_up) as f32 / (self.exp_to_next_lvl - last_up) as f32
         }
     }
@@ -516,10 +1413,14 @@ impl UpgradeType {
         match self {
             Self::NewAttack => Requirement::AttackSlots(current_level + 2),
             Self::IncUltRadius => Requirement::Score(30),
+            Self::IncUltDuration => Requirement::Score(30),
+            Self::IncUltDamage => Requirement::Score(100),
             Self::ReduceUltCooldown => Requirement::Score(100),
             Self::IncDeathTimer => Requirement::None,
             Self::ReduceAttackCooldown => Requirement::None,
+            Self::IncAttackDamage => Requirement::None,
             Self::UpgradeAttack => Requirement::None,
+            Self::BonusScore => Requirement::None,
         }
     }
 }
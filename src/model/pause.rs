@@ -0,0 +1,122 @@
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuAction {
+    Resume,
+    Settings,
+    GiveUp,
+    Restart,
+    MainMenu,
+}
+
+const PAUSE_OPTIONS: [(MenuAction, &str); 4] = [
+    (MenuAction::Resume, "Resume"),
+    (MenuAction::Settings, "Settings"),
+    (MenuAction::GiveUp, "Give up"),
+    (MenuAction::MainMenu, "Main menu"),
+];
+
+/// Pushed on top of the game, which does not update until it is popped.
+pub struct Pause {
+    geng: Geng,
+    assets: Rc<Assets>,
+    shared: Shared,
+    controls: InputMap,
+    result: Rc<Cell<Option<MenuAction>>>,
+    choice: usize,
+    camera: Camera2d,
+    transition: Option<geng::Transition>,
+}
+
+impl Pause {
+    pub fn new(
+        geng: &Geng,
+        assets: &Rc<Assets>,
+        shared: Shared,
+        controls: InputMap,
+        result: Rc<Cell<Option<MenuAction>>>,
+    ) -> Self {
+        Self {
+            geng: geng.clone(),
+            assets: assets.clone(),
+            shared,
+            controls,
+            result,
+            choice: 0,
+            camera: Camera2d {
+                center: Vec2::ZERO,
+                rotation: 0.0,
+                fov: 1080.0,
+            },
+            transition: None,
+        }
+    }
+
+    fn close(&mut self, action: MenuAction) {
+        self.result.set(Some(action));
+        self.shared.audio.play(SoundType::Select);
+        self.transition = Some(geng::Transition::Pop);
+    }
+
+    fn handle_input(&mut self, input: Input) {
+        if self.transition.is_some() {
+            return;
+        }
+        if self.controls.is_menu(input) {
+            self.close(MenuAction::Resume);
+        } else if let Some(direction) = self.controls.menu_direction(input) {
+            if direction.y != 0 {
+                self.choice = (self.choice as Coord - direction.y)
+                    .rem_euclid(PAUSE_OPTIONS.len() as Coord)
+                    as usize;
+                self.shared.audio.play(SoundType::Blip);
+            }
+        } else if self.controls.is_confirm(input) {
+            self.close(PAUSE_OPTIONS[self.choice].0);
+        }
+    }
+}
+
+impl geng::State for Pause {
+    fn update(&mut self, delta_time: f64) {
+        self.shared.audio.update(delta_time as f32);
+        let inputs = self.shared.gamepad.borrow_mut().poll();
+        for input in inputs {
+            self.handle_input(input);
+        }
+    }
+
+    fn draw(&mut self, framebuffer: &mut ugli::Framebuffer) {
+        ugli::clear(framebuffer, Some(Color::BLACK), None);
+        let framebuffer_size = framebuffer.size().map(|x| x as f32);
+        let framebuffer_size = vec2(
+            self.camera.fov / framebuffer_size.y * framebuffer_size.x,
+            self.camera.fov,
+        );
+        self.camera.center = framebuffer_size / 2.0;
+        let mut renderer = Renderer::new(&self.geng, &self.assets, &self.camera, framebuffer);
+
+        renderer.draw_text(
+            "PAUSED",
+            vec2(framebuffer_size.x / 2.0, framebuffer_size.y - 200.0),
+            vec2(0.5, 1.0),
+            60.0,
+            Color::WHITE,
+        );
+        let options = PAUSE_OPTIONS
+            .iter()
+            .map(|(_, name)| name.to_string())
+            .collect::<Vec<_>>();
+        renderer.draw_options(&options, self.choice, framebuffer_size / 2.0);
+    }
+
+    fn handle_event(&mut self, event: geng::Event) {
+        if let geng::Event::KeyDown { key } = event {
+            self.handle_input(Input::Key(key));
+        }
+    }
+
+    fn transition(&mut self) -> Option<geng::Transition> {
+        self.transition.take()
+    }
+}
@@ -0,0 +1,142 @@
+use super::*;
+
+/// Increase when the save format changes, and add a step to `Profile::migrate`.
+pub const PROFILE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    /// Format of the save, 0 if there was no profile yet.
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default)]
+    pub highscores: BTreeMap<String, Score>,
+    #[serde(default)]
+    pub stats: Statistics,
+    #[serde(default)]
+    pub currency: u32,
+    #[serde(default)]
+    pub unlocked: BTreeSet<Content>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Statistics {
+    pub runs: u32,
+    pub turns: u64,
+    pub total_score: u64,
+    pub best_score: Score,
+    pub best_level: u32,
+    pub kills: BTreeMap<EnemyType, u32>,
+}
+
+impl Profile {
+    pub fn load(rules: &Rules) -> AutoSave<Self> {
+        let mut profile: AutoSave<Self> =
+            AutoSave::load(static_path().join("profile.json").to_str().unwrap());
+        if profile.version < PROFILE_VERSION {
+            profile.migrate(rules);
+        }
+        profile
+    }
+
+    fn migrate(&mut self, rules: &Rules) {
+        if self.version == 0 {
+            // Before profiles only the high scores were saved, in a file per class
+            for class in &rules.classes.classes {
+                let file = rules.classes.highscore_file(&class.name);
+                let highscore: AutoSave<Score> =
+                    AutoSave::load(static_path().join(file).to_str().unwrap());
+                if *highscore > 0 {
+                    self.highscores.insert(class.name.clone(), *highscore);
+                    self.stats.best_score = self.stats.best_score.max(*highscore);
+                }
+            }
+            self.update_unlocks(rules);
+            self.version = 1;
+        }
+    }
+
+    pub fn highscore(&self, class: &str) -> Score {
+        self.highscores.get(class).copied().unwrap_or(0)
+    }
+
+    pub fn is_unlocked(&self, content: &Content, rules: &Rules) -> bool {
+        rules.unlocks.get(content).is_none() || self.unlocked.contains(content)
+    }
+
+    pub fn locked(&self, rules: &Rules) -> BTreeSet<Content> {
+        rules
+            .unlocks
+            .unlocks
+            .iter()
+            .map(|unlock| unlock.content.clone())
+            .filter(|content| !self.unlocked.contains(content))
+            .collect()
+    }
+
+    /// Adds the results of a finished run. Returns the currency earned.
+    pub fn record_run(&mut self, simulation: &Simulation, rules: &Rules) -> u32 {
+        let stats = &mut self.stats;
+        stats.runs += 1;
+        stats.turns += simulation.director.turn as u64;
+        stats.total_score += simulation.score as u64;
+        stats.best_score = stats.best_score.max(simulation.score);
+        stats.best_level = stats.best_level.max(simulation.experience.level);
+        for (typ, &count) in &simulation.kills {
+            *stats.kills.entry(typ.clone()).or_default() += count;
+        }
+        let highscore = self.highscores.entry(simulation.class.clone()).or_default();
+        *highscore = (*highscore).max(simulation.score);
+
+        let earned = (simulation.score as f32 * rules.unlocks.currency_per_score) as u32;
+        self.currency += earned;
+        self.update_unlocks(rules);
+        earned
+    }
+
+    /// Unlocks the free content whose requirements are met.
+    fn update_unlocks(&mut self, rules: &Rules) {
+        for unlock in &rules.unlocks.unlocks {
+            if unlock.cost == 0 && unlock.requirement.check(&self.stats) {
+                self.unlocked.insert(unlock.content.clone());
+            }
+        }
+    }
+
+    pub fn buy(&mut self, content: &Content, rules: &Rules) -> bool {
+        let unlock = match rules.unlocks.get(content) {
+            Some(unlock) => unlock,
+            None => return false,
+        };
+        if self.unlocked.contains(content)
+            || self.currency < unlock.cost
+            || !unlock.requirement.check(&self.stats)
+        {
+            return false;
+        }
+        self.currency -= unlock.cost;
+        self.unlocked.insert(content.clone());
+        true
+    }
+}
+
+impl Achievement {
+    pub fn check(&self, stats: &Statistics) -> bool {
+        match self {
+            Self::BestScore(score) => stats.best_score >= *score,
+            Self::BestLevel(level) => stats.best_level >= *level,
+            Self::Runs(runs) => stats.runs >= *runs,
+            Self::TotalKills(kills) => stats.kills.values().sum::<u32>() >= *kills,
+            Self::Kills { enemy, count } => stats.kills.get(enemy).copied().unwrap_or(0) >= *count,
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            Self::BestScore(score) => format!("Score {} points", score),
+            Self::BestLevel(level) => format!("Reach level {}", level),
+            Self::Runs(runs) => format!("Play {} runs", runs),
+            Self::TotalKills(kills) => format!("Kill {} enemies", kills),
+            Self::Kills { enemy, count } => format!("Kill {} {}s", count, enemy.0),
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use super::*;
+
+use serde::{Deserialize, Serialize};
+
+pub const REPLAY_MIN_SPEED: f32 = 0.25;
+pub const REPLAY_MAX_SPEED: f32 = 16.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Command {
+    Move(Position),
+    Ultimate,
+    Confirm,
+    Restart,
+    Choose(usize),
+    /// Teleports straight to the tile while using the teleport ultimate.
+    Target(Position),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayCommand {
+    pub time: f32,
+    pub move_time_left: f32,
+    pub command: Command,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub map: String,
+    pub class: String,
+    pub locked: BTreeSet<Content>,
+    pub highscore: Score,
+    pub commands: Vec<ReplayCommand>,
+}
+
+pub struct Playback {
+    pub replay: Replay,
+    pub next_command: usize,
+    pub time: f32,
+    pub speed: f32,
+}
+
+impl Replay {
+    pub fn new(
+        seed: u64,
+        highscore: Score,
+        map: &str,
+        class: &str,
+        locked: BTreeSet<Content>,
+    ) -> Self {
+        Self {
+            seed,
+            map: map.to_owned(),
+            class: class.to_owned(),
+            locked,
+            highscore,
+            commands: vec![],
+        }
+    }
+
+    pub fn record(&mut self, time: f32, move_time_left: f32, command: Command) {
+        self.commands.push(ReplayCommand {
+            time,
+            move_time_left,
+            command,
+        });
+    }
+
+    pub fn load(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+}
+
+impl Playback {
+    pub fn new(replay: Replay, speed: f32) -> Self {
+        Self {
+            replay,
+            next_command: 0,
+            time: 0.0,
+            speed: speed.clamp(REPLAY_MIN_SPEED, REPLAY_MAX_SPEED),
+        }
+    }
+
+    pub fn update(&mut self, delta_time: f32) -> Vec<ReplayCommand> {
+        self.time += delta_time;
+        let due = self.replay.commands[self.next_command..]
+            .iter()
+            .take_while(|command| command.time <= self.time)
+            .cloned()
+            .collect::<Vec<_>>();
+        self.next_command += due.len();
+        due
+    }
+
+    pub fn speed_up(&mut self) {
+        self.speed = (self.speed * 2.0).min(REPLAY_MAX_SPEED);
+    }
+
+    pub fn slow_down(&mut self) {
+        self.speed = (self.speed / 2.0).max(REPLAY_MIN_SPEED);
+    }
+}
@@ -0,0 +1,415 @@
+use super::*;
+
+use crate::audio::AudioSettings;
+use crate::input::{Input, InputMap, KeyPreset};
+
+const VOLUME_STEP: f64 = 0.1;
+const INTERPOLATION_TIMES: [f32; 4] = [0.05, 0.1, 0.2, 0.4];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub keys: KeyPreset,
+    /// Starts as the bindings of `keys`, and can be edited in the file.
+    pub controls: InputMap,
+    pub palette: PaletteType,
+    /// Longest time a unit takes to animate its queued moves.
+    pub interpolation_time: f32,
+    pub timer: TimerDisplay,
+}
+
+pub struct Saves {
+    pub profile: AutoSave<Profile>,
+    pub settings: AutoSave<Settings>,
+}
+
+impl Saves {
+    pub fn load(rules: &Rules) -> Self {
+        Self {
+            profile: Profile::load(rules),
+            settings: AutoSave::load(static_path().join("settings.json").to_str().unwrap()),
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            keys: KeyPreset::Arrows,
+            controls: InputMap::default(),
+            palette: PaletteType::Default,
+            interpolation_time: INTERPOLATION_MAX_TIME,
+            timer: TimerDisplay::Both,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaletteType {
+    Default,
+    HighContrast,
+    Colorblind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimerDisplay {
+    Both,
+    Bar,
+    Text,
+}
+
+pub struct Palette {
+    pub grid: Color<f32>,
+    pub wrap: Color<f32>,
+    pub wall: Color<f32>,
+    pub pit: Color<f32>,
+    pub slow: Color<f32>,
+    pub barrier: Color<f32>,
+    pub damage: Color<f32>,
+    pub enemy_damage: Color<f32>,
+    pub telegraph: Color<f32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SettingsItem {
+    MasterVolume,
+    SfxVolume,
+    UiVolume,
+    MusicVolume,
+    Keys,
+    Binding(usize),
+    MenuBinding,
+    Palette,
+    InterpolationTime,
+    Timer,
+    Back,
+}
+
+#[derive(Default)]
+pub struct SettingsMenu {
+    pub choice: usize,
+    /// Waiting for the input to bind to the selected action.
+    pub rebinding: bool,
+}
+
+fn settings_items(settings: &Settings) -> Vec<SettingsItem> {
+    let mut items = vec![
+        SettingsItem::MasterVolume,
+        SettingsItem::SfxVolume,
+        SettingsItem::UiVolume,
+        SettingsItem::MusicVolume,
+        SettingsItem::Keys,
+    ];
+    items.extend((0..settings.controls.bindings.len()).map(SettingsItem::Binding));
+    items.extend([
+        SettingsItem::MenuBinding,
+        SettingsItem::Palette,
+        SettingsItem::InterpolationTime,
+        SettingsItem::Timer,
+        SettingsItem::Back,
+    ]);
+    items
+}
+
+fn command_name(command: Command) -> &'static str {
+    match command {
+        Command::Move(direction) if direction.x < 0 => "Left",
+        Command::Move(direction) if direction.x > 0 => "Right",
+        Command::Move(direction) if direction.y < 0 => "Down",
+        Command::Move(_) => "Up",
+        Command::Ultimate => "Ultimate",
+        Command::Confirm => "Confirm",
+        Command::Restart => "Restart",
+        Command::Choose(_) => "Choose",
+        Command::Target(_) => "Target",
+    }
+}
+
+fn input_names(inputs: &[Input]) -> String {
+    if inputs.is_empty() {
+        return "None".to_owned();
+    }
+    inputs
+        .iter()
+        .map(|input| match input {
+            Input::Key(key) => format!("{:?}", key),
+            Input::Button(button) => format!("{:?}", button),
+        })
+        .collect::<Vec<_>>()
+        .join(" / ")
+}
+
+impl KeyPreset {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Arrows => "Arrows",
+            Self::Wasd => "WASD",
+            Self::Vim => "HJKL",
+        }
+    }
+
+    fn cycle(&self, delta: Coord) -> Self {
+        cycle(&[Self::Arrows, Self::Wasd, Self::Vim], self, delta)
+    }
+}
+
+impl PaletteType {
+    pub fn palette(&self) -> Palette {
+        match self {
+            Self::Default => Palette {
+                grid: GRID_COLOR,
+                wrap: WRAP_COLOR,
+                wall: WALL_COLOR,
+                pit: PIT_COLOR,
+                slow: SLOW_COLOR,
+                barrier: BARRIER_COLOR,
+                damage: DAMAGE_COLOR,
+                enemy_damage: ENEMY_DAMAGE_COLOR,
+                telegraph: ENEMY_TELEGRAPH_COLOR,
+            },
+            Self::HighContrast => Palette {
+                grid: Color::WHITE,
+                wrap: Color::CYAN,
+                wall: Color::WHITE,
+                pit: Color {
+                    r: 0.3,
+                    g: 0.0,
+                    b: 0.0,
+                    a: 1.0,
+                },
+                slow: Color {
+                    r: 0.0,
+                    g: 0.4,
+                    b: 1.0,
+                    a: 0.7,
+                },
+                barrier: Color {
+                    r: 1.0,
+                    g: 0.0,
+                    b: 1.0,
+                    a: 0.7,
+                },
+                damage: Color::RED,
+                enemy_damage: Color::YELLOW,
+                telegraph: Color::YELLOW,
+            },
+            // Blue and orange instead of red and green
+            Self::Colorblind => Palette {
+                grid: GRID_COLOR,
+                wrap: WRAP_COLOR,
+                wall: WALL_COLOR,
+                pit: PIT_COLOR,
+                slow: SLOW_COLOR,
+                barrier: BARRIER_COLOR,
+                damage: Color {
+                    r: 0.0,
+                    g: 0.45,
+                    b: 0.7,
+                    a: 1.0,
+                },
+                enemy_damage: Color {
+                    r: 0.9,
+                    g: 0.6,
+                    b: 0.0,
+                    a: 1.0,
+                },
+                telegraph: Color {
+                    r: 0.9,
+                    g: 0.6,
+                    b: 0.0,
+                    a: 0.5,
+                },
+            },
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Default => "Default",
+            Self::HighContrast => "High contrast",
+            Self::Colorblind => "Colorblind",
+        }
+    }
+
+    fn cycle(&self, delta: Coord) -> Self {
+        cycle(
+            &[Self::Default, Self::HighContrast, Self::Colorblind],
+            self,
+            delta,
+        )
+    }
+}
+
+impl TimerDisplay {
+    pub fn show_text(&self) -> bool {
+        matches!(self, Self::Both | Self::Text)
+    }
+
+    pub fn show_bar(&self) -> bool {
+        matches!(self, Self::Both | Self::Bar)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Both => "Bar and text",
+            Self::Bar => "Bar",
+            Self::Text => "Text",
+        }
+    }
+
+    fn cycle(&self, delta: Coord) -> Self {
+        cycle(&[Self::Both, Self::Bar, Self::Text], self, delta)
+    }
+}
+
+fn cycle<T: Copy + PartialEq>(options: &[T], current: &T, delta: Coord) -> T {
+    let index = options
+        .iter()
+        .position(|option| option == current)
+        .unwrap_or(0) as Coord;
+    options[(index + delta).rem_euclid(options.len() as Coord) as usize]
+}
+
+impl SettingsMenu {
+    /// Returns whether the menu should be closed.
+    pub fn handle_input(&mut self, input: Input, settings: &mut Settings, audio: &Audio) -> bool {
+        let items = settings_items(settings);
+        self.choice = self.choice.min(items.len() - 1);
+        let item = items[self.choice];
+        if self.rebinding {
+            self.rebinding = false;
+            match item {
+                SettingsItem::Binding(index) => settings.controls.rebind(Some(index), input),
+                SettingsItem::MenuBinding => settings.controls.rebind(None, input),
+                _ => {}
+            }
+            audio.play(SoundType::Select);
+            return false;
+        }
+        if settings.controls.is_menu(input) {
+            return true;
+        }
+        if settings.controls.is_confirm(input) {
+            if let SettingsItem::Binding(_) | SettingsItem::MenuBinding = item {
+                self.rebinding = true;
+                audio.play(SoundType::Select);
+            }
+            return matches!(item, SettingsItem::Back);
+        }
+        let direction = match settings.controls.menu_direction(input) {
+            Some(direction) => direction,
+            None => return false,
+        };
+        if direction.y != 0 {
+            self.choice =
+                (self.choice as Coord - direction.y).rem_euclid(items.len() as Coord) as usize;
+            audio.play(SoundType::Blip);
+            return false;
+        }
+
+        let delta = direction.x;
+        let mut audio_settings = audio.settings();
+        let change_volume = |volume: &mut f64| {
+            *volume = (*volume + VOLUME_STEP * delta as f64).clamp(0.0, 1.0);
+        };
+        match item {
+            SettingsItem::MasterVolume => change_volume(&mut audio_settings.master),
+            SettingsItem::SfxVolume => change_volume(&mut audio_settings.sfx),
+            SettingsItem::UiVolume => change_volume(&mut audio_settings.ui),
+            SettingsItem::MusicVolume => change_volume(&mut audio_settings.music),
+            SettingsItem::Keys => {
+                settings.keys = settings.keys.cycle(delta);
+                settings.controls = InputMap::new(settings.keys);
+            }
+            SettingsItem::Palette => settings.palette = settings.palette.cycle(delta),
+            SettingsItem::InterpolationTime => {
+                settings.interpolation_time =
+                    cycle(&INTERPOLATION_TIMES, &settings.interpolation_time, delta);
+            }
+            SettingsItem::Timer => settings.timer = settings.timer.cycle(delta),
+            SettingsItem::Binding(_) | SettingsItem::MenuBinding | SettingsItem::Back => {
+                return false
+            }
+        }
+        audio.set_settings(audio_settings);
+        audio.play(SoundType::Blip);
+        false
+    }
+
+    pub fn draw(
+        &self,
+        renderer: &mut Renderer,
+        framebuffer_size: Vec2<f32>,
+        settings: &Settings,
+        audio_settings: &AudioSettings,
+    ) {
+        let items = settings_items(settings);
+        let size = vec2(MENU_WIDTH, MENU_LINE_HEIGHT * items.len() as f32);
+        let aabb = AABB::point(framebuffer_size / 2.0).extend_symmetric(size / 2.0);
+        renderer.draw_aabb(
+            aabb.extend_uniform(UPGRADE_EXTRA_SPACE),
+            UPGRADE_BACKGROUND_COLOR,
+        );
+        renderer.draw_text(
+            "SETTINGS",
+            vec2(aabb.center().x, aabb.y_max + UPGRADE_EXTRA_SPACE / 2.0),
+            vec2(0.5, 0.5),
+            MENU_LINE_HEIGHT * 0.6,
+            UPGRADE_TEXT_COLOR,
+        );
+        let volume = |volume: f64| format!("{:.0}%", volume * 100.0);
+        for (i, item) in items.iter().enumerate() {
+            let line = AABB::from_corners(
+                vec2(aabb.x_min, aabb.y_max - MENU_LINE_HEIGHT * (i + 1) as f32),
+                vec2(aabb.x_max, aabb.y_max - MENU_LINE_HEIGHT * i as f32),
+            );
+            if i == self.choice {
+                renderer.draw_aabb(line, UPGRADE_SELECTED_COLOR);
+            }
+            let (name, mut value) = match item {
+                SettingsItem::MasterVolume => ("Volume", volume(audio_settings.master)),
+                SettingsItem::SfxVolume => ("Sound effects", volume(audio_settings.sfx)),
+                SettingsItem::UiVolume => ("Menu sounds", volume(audio_settings.ui)),
+                SettingsItem::MusicVolume => ("Music", volume(audio_settings.music)),
+                SettingsItem::Keys => (
+                    "Keys",
+                    if settings.controls == InputMap::new(settings.keys) {
+                        settings.keys.name().to_owned()
+                    } else {
+                        "Custom".to_owned()
+                    },
+                ),
+                SettingsItem::Binding(index) => {
+                    let binding = &settings.controls.bindings[*index];
+                    (command_name(binding.command), input_names(&binding.inputs))
+                }
+                SettingsItem::MenuBinding => ("Menu", input_names(&settings.controls.menu)),
+                SettingsItem::Palette => ("Colors", settings.palette.name().to_owned()),
+                SettingsItem::InterpolationTime => (
+                    "Animation time",
+                    format!("{:.2}s", settings.interpolation_time),
+                ),
+                SettingsItem::Timer => ("Timer", settings.timer.name().to_owned()),
+                SettingsItem::Back => ("Back", String::new()),
+            };
+            if self.rebinding && i == self.choice {
+                value = "Press a key or button".to_owned();
+            }
+            let font_size = MENU_LINE_HEIGHT * 0.5;
+            renderer.draw_text(
+                name,
+                vec2(line.x_min + font_size, line.center().y),
+                vec2(0.0, 0.5),
+                font_size,
+                UPGRADE_TEXT_COLOR,
+            );
+            renderer.draw_text(
+                &value,
+                vec2(line.x_max - font_size, line.center().y),
+                vec2(1.0, 0.5),
+                font_size,
+                UPGRADE_TEXT_COLOR,
+            );
+        }
+    }
+}
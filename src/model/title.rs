@@ -0,0 +1,199 @@
+use super::*;
+
+#[derive(Debug, Clone, Copy)]
+enum TitleOption {
+    Play,
+    QuickPlay,
+    Map,
+    Settings,
+}
+
+const TITLE_OPTIONS: [TitleOption; 4] = [
+    TitleOption::Play,
+    TitleOption::QuickPlay,
+    TitleOption::Map,
+    TitleOption::Settings,
+];
+
+pub struct TitleScreen {
+    geng: Geng,
+    assets: Rc<Assets>,
+    seed: u64,
+    map: usize,
+    choice: usize,
+    settings_menu: Option<SettingsMenu>,
+    shared: Shared,
+    camera: Camera2d,
+    transition: Option<geng::Transition>,
+}
+
+impl TitleScreen {
+    pub fn new(geng: &Geng, assets: &Rc<Assets>, seed: u64, map: &str, shared: Shared) -> Self {
+        shared.audio.play_music(MusicTrack::Menu);
+        Self {
+            geng: geng.clone(),
+            assets: assets.clone(),
+            seed,
+            map: assets
+                .rules
+                .maps
+                .maps
+                .iter()
+                .position(|definition| definition.name == map)
+                .unwrap_or(0),
+            choice: 0,
+            settings_menu: None,
+            shared,
+            camera: Camera2d {
+                center: Vec2::ZERO,
+                rotation: 0.0,
+                fov: 1080.0,
+            },
+            transition: None,
+        }
+    }
+
+    fn change_map(&mut self, delta: Coord) {
+        let count = self.assets.rules.maps.maps.len() as Coord;
+        self.map = (self.map as Coord + delta).rem_euclid(count) as usize;
+        self.shared.audio.play(SoundType::Blip);
+    }
+
+    fn select(&mut self) {
+        let map = self.assets.rules.maps.maps[self.map].name.clone();
+        let state: Box<dyn geng::State> = match TITLE_OPTIONS[self.choice] {
+            TitleOption::Play => Box::new(ClassSelect::new(
+                &self.geng,
+                &self.assets,
+                self.seed,
+                &map,
+                self.shared.clone(),
+            )),
+            TitleOption::QuickPlay => Box::new(GameState::new(
+                &self.geng,
+                &self.assets,
+                self.seed,
+                &map,
+                &self.assets.rules.classes.default,
+                self.shared.clone(),
+            )),
+            TitleOption::Map => {
+                self.change_map(1);
+                return;
+            }
+            TitleOption::Settings => {
+                self.settings_menu = Some(SettingsMenu::default());
+                self.shared.audio.play(SoundType::Select);
+                return;
+            }
+        };
+        self.shared.audio.play(SoundType::Select);
+        self.transition = Some(geng::Transition::Switch(state));
+    }
+
+    fn handle_input(&mut self, input: Input) {
+        if self.transition.is_some() {
+            return;
+        }
+        if let Some(menu) = &mut self.settings_menu {
+            if menu.handle_input(
+                input,
+                &mut self.shared.saves.borrow_mut().settings,
+                &self.shared.audio,
+            ) {
+                self.settings_menu = None;
+            }
+            return;
+        }
+
+        let controls = self.shared.saves.borrow().settings.controls.clone();
+        if let Some(direction) = controls.menu_direction(input) {
+            if direction.y != 0 {
+                self.choice = (self.choice as Coord - direction.y)
+                    .rem_euclid(TITLE_OPTIONS.len() as Coord)
+                    as usize;
+                self.shared.audio.play(SoundType::Blip);
+            } else if let TitleOption::Map = TITLE_OPTIONS[self.choice] {
+                self.change_map(direction.x);
+            }
+        } else if controls.is_confirm(input) {
+            self.select();
+        }
+    }
+}
+
+impl geng::State for TitleScreen {
+    fn update(&mut self, delta_time: f64) {
+        self.shared.audio.update(delta_time as f32);
+        let inputs = self.shared.gamepad.borrow_mut().poll();
+        for input in inputs {
+            self.handle_input(input);
+        }
+    }
+
+    fn draw(&mut self, framebuffer: &mut ugli::Framebuffer) {
+        ugli::clear(framebuffer, Some(Color::BLACK), None);
+        let framebuffer_size = framebuffer.size().map(|x| x as f32);
+        let framebuffer_size = vec2(
+            self.camera.fov / framebuffer_size.y * framebuffer_size.x,
+            self.camera.fov,
+        );
+        self.camera.center = framebuffer_size / 2.0;
+        let mut renderer = Renderer::new(&self.geng, &self.assets, &self.camera, framebuffer);
+
+        renderer.draw_text(
+            "Delay the inevitable",
+            vec2(framebuffer_size.x / 2.0, framebuffer_size.y - 200.0),
+            vec2(0.5, 1.0),
+            80.0,
+            Color::WHITE,
+        );
+
+        let saves = self.shared.saves.borrow();
+        renderer.draw_text(
+            &format!("Coins: {}", saves.profile.currency),
+            vec2(10.0, framebuffer_size.y - 10.0),
+            vec2(0.0, 1.0),
+            30.0,
+            Color::GRAY,
+        );
+        renderer.draw_text(
+            &format!("Best: {}", saves.profile.stats.best_score),
+            vec2(10.0, framebuffer_size.y - 50.0),
+            vec2(0.0, 1.0),
+            30.0,
+            Color::GRAY,
+        );
+
+        let rules = &self.assets.rules;
+        let options = TITLE_OPTIONS
+            .iter()
+            .map(|option| match option {
+                TitleOption::Play => "Play".to_owned(),
+                TitleOption::QuickPlay => format!("Quick play ({})", rules.classes.default),
+                TitleOption::Map => format!("< Map: {} >", rules.maps.maps[self.map].name),
+                TitleOption::Settings => "Settings".to_owned(),
+            })
+            .collect::<Vec<_>>();
+        renderer.draw_options(&options, self.choice, framebuffer_size / 2.0);
+
+        if let Some(menu) = &self.settings_menu {
+            menu.draw(
+                &mut renderer,
+                framebuffer_size,
+                &saves.settings,
+                &self.shared.audio.settings(),
+            );
+        }
+    }
+
+    fn handle_event(&mut self, event: geng::Event) {
+        if let geng::Event::KeyDown { key } = event {
+            self.handle_input(Input::Key(key));
+        }
+    }
+
+    fn transition(&mut self) -> Option<geng::Transition> {
+        self.transition.take()
+    }
+}
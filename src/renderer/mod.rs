@@ -1,10 +1,11 @@
 use geng::{draw_2d::ColoredVertex, Draw2d};
 
 use crate::model::{
-    Attack, Teleport, Time, ATTACK_COOLDOWN_BACKGROUND_COLOR, ATTACK_COOLDOWN_BAR_EXTRA_SPACE,
+    Attack, Time, Ultimate, ATTACK_COOLDOWN_BACKGROUND_COLOR, ATTACK_COOLDOWN_BAR_EXTRA_SPACE,
     ATTACK_COOLDOWN_COLOR, ATTACK_COOLDOWN_HEIGHT, ATTACK_HIGHLIGHT_COLOR, ATTACK_HIGHLIGHT_WIDTH,
     ATTACK_LOCK_TEXT_COLOR, LEVEL_BACKGROUND_BACK_COLOR, LEVEL_BACKGROUND_FRONT_COLOR,
-    LEVEL_INNER_SPACE, LEVEL_OUTER_SPACE, LEVEL_TEXT_COLOR, SLOTS_REQUIREMENTS,
+    LEVEL_INNER_SPACE, LEVEL_OUTER_SPACE, LEVEL_TEXT_COLOR, MENU_LINE_HEIGHT, MENU_WIDTH,
+    SLOTS_REQUIREMENTS, UPGRADE_SELECTED_COLOR, UPGRADE_TEXT_COLOR,
 };
 
 use super::*;
@@ -261,7 +262,7 @@ impl<'a, 'f, C: geng::AbstractCamera2d> Renderer<'a, 'f, C> {
 
     pub fn draw_ultimate(
         &mut self,
-        ultimate: &Teleport,
+        ultimate: &Ultimate,
         aabb: AABB<f32>,
         border_width: f32,
         border_color: Color<f32>,
@@ -273,8 +274,8 @@ impl<'a, 'f, C: geng::AbstractCamera2d> Renderer<'a, 'f, C> {
             .extend_up(ATTACK_COOLDOWN_HEIGHT - aabb.height() + aabb.width() * 0.1)
             .extend_uniform(-aabb.width() * 0.05);
         self.draw_cooldown(
-            ultimate.action.next + 1,
-            ultimate.action.cooldown + 1,
+            ultimate.action().next + 1,
+            ultimate.action().cooldown + 1,
             cd_aabb,
         );
         let aabb = aabb
@@ -285,7 +286,7 @@ impl<'a, 'f, C: geng::AbstractCamera2d> Renderer<'a, 'f, C> {
         let (scale, offset) = scale_align_aabb(boundary.map(|x| x as f32), aabb);
         let aabb = aabb.translate(offset);
         self.draw_text(
-            "ULTIMATE",
+            &ultimate.name().to_uppercase(),
             vec2(aabb.center().x, aabb.top_left().y + 3.0 * font_size),
             vec2(0.5, 0.0),
             font_size,
@@ -310,10 +311,20 @@ impl<'a, 'f, C: geng::AbstractCamera2d> Renderer<'a, 'f, C> {
 
         for pos in ultimate
             .deltas()
+            .filter(|&pos| pos != Vec2::ZERO)
             .map(|pos| logic::grid_cell_aabb(pos, tile_size).center())
         {
             self.draw_circle(pos + aabb.center(), scale * 0.1, Color::MAGENTA);
         }
+        if let Some(turns) = ultimate.turns() {
+            self.draw_text(
+                &format!("{} turns", turns),
+                vec2(aabb.center().x, aabb.y_min),
+                vec2(0.5, 0.0),
+                font_size,
+                Color::MAGENTA,
+            );
+        }
         self.draw_circle(
             aabb.center(),
             // scale / 2.0 * 0.7,
@@ -358,6 +369,27 @@ impl<'a, 'f, C: geng::AbstractCamera2d> Renderer<'a, 'f, C> {
             .draw_2d(self.geng, self.framebuffer, self.camera);
     }
 
+    pub fn draw_options(&mut self, options: &[String], choice: usize, center: Vec2<f32>) {
+        let size = vec2(MENU_WIDTH, MENU_LINE_HEIGHT * options.len() as f32);
+        let aabb = AABB::point(center).extend_symmetric(size / 2.0);
+        for (i, option) in options.iter().enumerate() {
+            let line = AABB::from_corners(
+                vec2(aabb.x_min, aabb.y_max - MENU_LINE_HEIGHT * (i + 1) as f32),
+                vec2(aabb.x_max, aabb.y_max - MENU_LINE_HEIGHT * i as f32),
+            );
+            if i == choice {
+                self.draw_aabb(line, UPGRADE_SELECTED_COLOR);
+            }
+            self.draw_text(
+                option,
+                line.center(),
+                vec2(0.5, 0.5),
+                MENU_LINE_HEIGHT * 0.5,
+                UPGRADE_TEXT_COLOR,
+            );
+        }
+    }
+
     pub fn draw_level(&mut self, level: u32, aabb: AABB<f32>) {
         let aabb = AABB::point(aabb.center()).extend_uniform(aabb.width().min(aabb.height()) / 2.0);
         self.draw_circle(